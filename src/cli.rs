@@ -0,0 +1,438 @@
+//command line parsing, kept by hand to stay dependency free
+use crate::config::{self, Config};
+use crate::encoding;
+use crate::livereload::Reloader;
+use crate::mime;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Minimal web server that serves the contents of a directory
+
+Usage: mini-server [OPTIONS] [ROOT]
+
+Environment variables are only read for options not given on the command line.
+
+Arguments:
+  [ROOT]                 directory to serve [env: MINI_SERVER_ROOT] [default: .]
+
+Options:
+  -p, --port <PORT>      port to listen on, 0 picks a free one
+                         [env: MINI_SERVER_PORT or PORT] [default: 3000]
+      --port-retries <N> try up to N following ports when PORT is taken
+                         [env: MINI_SERVER_PORT_RETRIES] [default: 0]
+      --port-file <FILE> write the bound port to FILE [env: MINI_SERVER_PORT_FILE]
+  -b, --bind <ADDR>      address to listen on, repeat or comma separate for several
+                         use 0.0.0.0 or :: for all interfaces
+                         [env: MINI_SERVER_BIND] [default: 127.0.0.1]
+      --cert <FILE>      PEM certificate chain, serves HTTPS together with --key
+                         reloaded when the file changes [env: MINI_SERVER_TLS_CERT]
+      --key <FILE>       PEM private key for --cert [env: MINI_SERVER_TLS_KEY]
+      --self-signed      serve HTTPS with a generated development certificate for
+                         localhost and this machine's addresses, signed by a
+                         local CA you can trust once [env: MINI_SERVER_SELF_SIGNED]
+      --self-signed-dir <DIR>
+                         where the generated CA and certificate are kept
+                         [env: MINI_SERVER_SELF_SIGNED_DIR]
+                         [default: ~/.cache/mini-server]
+      --redirect-http <PORT>
+                         also listen for plain HTTP on PORT and redirect it to
+                         HTTPS [env: MINI_SERVER_REDIRECT_HTTP]
+      --h2c              accept HTTP/2 with prior knowledge on plain HTTP, HTTPS
+                         always offers it through ALPN [env: MINI_SERVER_H2C]
+      --h2-max-streams <N>
+                         concurrent HTTP/2 streams per connection
+                         [env: MINI_SERVER_H2_MAX_STREAMS] [default: unlimited]
+      --h2-stream-window <SIZE>
+                         initial HTTP/2 stream window, e.g. 64k
+                         [env: MINI_SERVER_H2_STREAM_WINDOW] [default: 1m]
+      --h2-connection-window <SIZE>
+                         initial HTTP/2 connection window
+                         [env: MINI_SERVER_H2_CONNECTION_WINDOW] [default: 1m]
+  -o, --open             open the server url in a browser once listening
+  -q, --quiet            don't log requests [env: MINI_SERVER_QUIET]
+      --index <NAMES>    comma separated index file names
+                         [env: MINI_SERVER_INDEX_FILES]
+                         [default: index.html,index.htm]
+      --spa <FILE>       serve FILE for page loads that would 404
+                         [env: MINI_SERVER_SPA_FALLBACK]
+      --spa-assets       also use the SPA fallback for paths with an extension
+                         [env: MINI_SERVER_SPA_FALLBACK_ASSETS]
+      --symlinks <POLICY>
+                         never, always or within-root [env: MINI_SERVER_SYMLINKS]
+                         [default: within-root]
+      --hidden           list and serve dotfiles [env: MINI_SERVER_HIDDEN]
+      --deny <GLOB>      answer 404 for matching paths, e.g. '*.key' or '.git/**'
+                         repeat or comma separate for several, adds to the
+                         patterns from [env: MINI_SERVER_DENY]
+      --mime-types <FILE>
+                         load extra types from an Apache or nginx style
+                         mime.types file [env: MINI_SERVER_MIME_TYPES]
+      --mime <EXT=TYPE>  serve files ending in .EXT as TYPE, repeatable
+      --sniff            guess the type of files without an extension from their
+                         contents [env: MINI_SERVER_SNIFF]
+      --no-precompressed don't look for .br, .zst and .gz siblings of files
+                         [env: MINI_SERVER_PRECOMPRESSED=0]
+      --no-compress      don't compress responses on the fly
+                         [env: MINI_SERVER_COMPRESS=0]
+      --compress-min-size <SIZE>
+                         smallest file worth compressing, e.g. 1k
+                         [env: MINI_SERVER_COMPRESS_MIN_SIZE] [default: 1024]
+      --compress-max-size <SIZE>
+                         largest file compressed on the fly, bigger ones are
+                         sent as they are
+                         [env: MINI_SERVER_COMPRESS_MAX_SIZE] [default: 8m]
+      --compress-level <LEVEL>
+                         fastest, default, best or a number for the codec,
+                         default is brotli 4, zstd 3 and gzip 6
+                         [env: MINI_SERVER_COMPRESS_LEVEL] [default: default]
+      --etag-hash        derive ETags from file contents
+                         [env: MINI_SERVER_ETAG_HASH]
+      --writable         accept PUT and uploads from the directory listing
+                         [env: MINI_SERVER_WRITABLE]
+      --max-upload <SIZE>
+                         largest body a write may send [env: MINI_SERVER_MAX_UPLOAD]
+                         [default: 100m]
+      --overwrite        let writes replace existing files
+                         [env: MINI_SERVER_OVERWRITE]
+      --live-reload      reload open pages when files under ROOT change, css
+                         changes are applied without a reload
+                         [env: MINI_SERVER_LIVE_RELOAD]
+      --live-reload-interval <TIME>
+                         how often the root is scanned for changes, e.g. 250ms
+                         or 2s [env: MINI_SERVER_LIVE_RELOAD_INTERVAL]
+                         [default: 500ms]
+  -h, --help             print this help
+  -V, --version          print the version
+";
+
+pub enum Action {
+    Serve(Box<Config>),
+    Help,
+    Version,
+}
+
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Action, String> {
+    let mut config = Config::default();
+    //environment variables the command line overrides, they aren't even looked at
+    let mut given = vec![];
+    let mut root: Option<PathBuf> = None;
+    let mut bind = vec![];
+    let mut deny = vec![];
+    let mut mime_overrides = vec![];
+    while let Some(arg) = args.next() {
+        //support both --name value and --name=value
+        let (name, mut inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => {
+                (name.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || match inline.take().or_else(|| args.next()) {
+            Some(value) => Ok(value),
+            None => Err(format!("{} needs a value", name)),
+        };
+        given.extend(env_name(&name));
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "-V" | "--version" => return Ok(Action::Version),
+            "-p" | "--port" => {
+                config.port = config::parse_port(&value()?).map_err(|e| format!("--port: {}", e))?
+            }
+            "--port-retries" => {
+                config.port_retries = config::parse_retries(&value()?)
+                    .map_err(|e| format!("--port-retries: {}", e))?
+            }
+            "--port-file" => config.port_file = Some(PathBuf::from(value()?)),
+            "-b" | "--bind" => {
+                bind.extend(config::parse_bind(&value()?).map_err(|e| format!("--bind: {}", e))?)
+            }
+            "--cert" => config.cert = Some(PathBuf::from(value()?)),
+            "--key" => config.key = Some(PathBuf::from(value()?)),
+            "--self-signed" => config.self_signed = true,
+            "--self-signed-dir" => config.self_signed_dir = Some(PathBuf::from(value()?)),
+            "--redirect-http" => {
+                config.redirect_http = Some(
+                    config::parse_port(&value()?).map_err(|e| format!("--redirect-http: {}", e))?,
+                )
+            }
+            "--h2c" => config.h2c = true,
+            "--h2-max-streams" => {
+                config.h2_max_streams = Some(
+                    config::parse_streams(&value()?)
+                        .map_err(|e| format!("--h2-max-streams: {}", e))?,
+                )
+            }
+            "--h2-stream-window" => {
+                config.h2_stream_window = Some(
+                    config::parse_window(&value()?)
+                        .map_err(|e| format!("--h2-stream-window: {}", e))?,
+                )
+            }
+            "--h2-connection-window" => {
+                config.h2_connection_window = Some(
+                    config::parse_window(&value()?)
+                        .map_err(|e| format!("--h2-connection-window: {}", e))?,
+                )
+            }
+            "-o" | "--open" => config.open = true,
+            "-q" | "--quiet" => config.quiet = true,
+            "--index" => config.index_files = config::list(&value()?),
+            "--spa" => config.spa_fallback = Some(value()?),
+            "--spa-assets" => config.spa_assets = true,
+            "--symlinks" => {
+                config.symlinks = value()?.parse().map_err(|e| format!("--symlinks: {}", e))?
+            }
+            "--hidden" => config.hidden = true,
+            "--deny" => deny.extend(config::list(&value()?)),
+            "--mime-types" => {
+                let path = PathBuf::from(value()?);
+                config
+                    .load_mime_types(&path)
+                    .map_err(|e| format!("--mime-types: {}", e))?
+            }
+            "--mime" => mime_overrides
+                .push(mime::parse_override(&value()?).map_err(|e| format!("--mime: {}", e))?),
+            "--sniff" => config.sniff = true,
+            "--no-precompressed" => config.precompressed = false,
+            "--no-compress" => config.compress = false,
+            "--compress-min-size" => {
+                config.compress_min_size = config::parse_size(&value()?)
+                    .map_err(|e| format!("--compress-min-size: {}", e))?
+            }
+            "--compress-max-size" => {
+                config.compress_max_size = config::parse_size(&value()?)
+                    .map_err(|e| format!("--compress-max-size: {}", e))?
+            }
+            "--compress-level" => {
+                config.compress_level = encoding::parse_level(&value()?)
+                    .map_err(|e| format!("--compress-level: {}", e))?
+            }
+            "--etag-hash" => config.etag_hash = true,
+            "--writable" => config.writable = true,
+            "--max-upload" => {
+                config.max_upload =
+                    config::parse_size(&value()?).map_err(|e| format!("--max-upload: {}", e))?
+            }
+            "--overwrite" => config.overwrite = true,
+            "--live-reload" => config.live_reload = Some(Reloader::default()),
+            "--live-reload-interval" => {
+                config.live_reload_interval = config::parse_interval(&value()?)
+                    .map_err(|e| format!("--live-reload-interval: {}", e))?
+            }
+            "--" => {
+                if let Ok(path) = value() {
+                    set_root(&mut root, path)?;
+                }
+            }
+            _ if name.starts_with('-') && name.len() > 1 => {
+                return Err(format!("unknown option {}", name))
+            }
+            _ => set_root(&mut root, arg)?,
+        }
+        //flags would otherwise drop it silently, and --writable=false must not mean on
+        if inline.is_some() {
+            return Err(format!("{} doesn't take a value", name));
+        }
+    }
+    if root.is_some() {
+        given.push("ROOT");
+    }
+    config.apply_env(&given)?;
+    if let Some(root) = root {
+        config.root = root;
+    }
+    if !bind.is_empty() {
+        config.bind = bind;
+    }
+    //patterns from the command line add to the ones from MINI_SERVER_DENY
+    config.deny.extend(deny);
+    //single overrides beat anything from a mime.types file, wherever it appeared
+    config.mime_overrides.extend(mime_overrides);
+    config.validate()?;
+    Ok(Action::Serve(Box::new(config)))
+}
+
+//the variable (without its MINI_SERVER_ prefix) an option stands in for
+fn env_name(option: &str) -> Option<&'static str> {
+    Some(match option {
+        "-p" | "--port" => "PORT",
+        "--port-retries" => "PORT_RETRIES",
+        "--port-file" => "PORT_FILE",
+        "-b" | "--bind" => "BIND",
+        "--cert" => "TLS_CERT",
+        "--key" => "TLS_KEY",
+        "--self-signed" => "SELF_SIGNED",
+        "--self-signed-dir" => "SELF_SIGNED_DIR",
+        "--redirect-http" => "REDIRECT_HTTP",
+        "--h2c" => "H2C",
+        "--h2-max-streams" => "H2_MAX_STREAMS",
+        "--h2-stream-window" => "H2_STREAM_WINDOW",
+        "--h2-connection-window" => "H2_CONNECTION_WINDOW",
+        "-q" | "--quiet" => "QUIET",
+        "--index" => "INDEX_FILES",
+        "--spa" => "SPA_FALLBACK",
+        "--spa-assets" => "SPA_FALLBACK_ASSETS",
+        "--symlinks" => "SYMLINKS",
+        "--hidden" => "HIDDEN",
+        "--mime-types" => "MIME_TYPES",
+        "--sniff" => "SNIFF",
+        "--no-precompressed" => "PRECOMPRESSED",
+        "--no-compress" => "COMPRESS",
+        "--compress-min-size" => "COMPRESS_MIN_SIZE",
+        "--compress-max-size" => "COMPRESS_MAX_SIZE",
+        "--compress-level" => "COMPRESS_LEVEL",
+        "--etag-hash" => "ETAG_HASH",
+        "--writable" => "WRITABLE",
+        "--max-upload" => "MAX_UPLOAD",
+        "--overwrite" => "OVERWRITE",
+        "--live-reload" => "LIVE_RELOAD",
+        "--live-reload-interval" => "LIVE_RELOAD_INTERVAL",
+        //--deny adds to the variable and --mime has none
+        _ => return None,
+    })
+}
+
+fn set_root(root: &mut Option<PathBuf>, path: String) -> Result<(), String> {
+    match root {
+        Some(_) => Err(format!(
+            "unexpected argument {}, only one ROOT can be served",
+            path
+        )),
+        None => {
+            *root = Some(PathBuf::from(path));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::SymlinkPolicy;
+    use std::env;
+    use std::net::IpAddr;
+    use std::sync::Mutex;
+
+    //parse reads the environment, tests that change it can't overlap with others
+    static ENV: Mutex<()> = Mutex::new(());
+
+    fn parse_args(args: &[&str]) -> Result<Config, String> {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        run(args)
+    }
+
+    fn run(args: &[&str]) -> Result<Config, String> {
+        match parse(args.iter().map(|a| a.to_string()))? {
+            Action::Serve(config) => Ok(*config),
+            _ => panic!("expected to serve"),
+        }
+    }
+
+    #[test]
+    fn accepts_both_value_forms() {
+        let config = parse_args(&["--port", "8080", "--bind=::1,127.0.0.1", "site"]).unwrap();
+        assert_eq!(config.port, 8080);
+        assert_eq!(
+            config.bind,
+            [
+                "::1".parse::<IpAddr>().unwrap(),
+                "127.0.0.1".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.root, PathBuf::from("site"));
+
+        let config = parse_args(&[
+            "--port=8081",
+            "--deny=*.key",
+            "--deny",
+            ".env",
+            "--writable",
+        ])
+        .unwrap();
+        assert_eq!(config.port, 8081);
+        assert_eq!(config.deny, ["*.key", ".env"]);
+        assert!(config.writable);
+    }
+
+    #[test]
+    fn flags_refuse_values() {
+        assert_eq!(
+            parse_args(&["--writable=false"]).err().unwrap(),
+            "--writable doesn't take a value"
+        );
+        assert_eq!(
+            parse_args(&["--hidden=no"]).err().unwrap(),
+            "--hidden doesn't take a value"
+        );
+        assert_eq!(
+            parse_args(&["--port"]).err().unwrap(),
+            "--port needs a value"
+        );
+        assert!(parse_args(&["--port=abc"]).is_err());
+    }
+
+    #[test]
+    fn refuses_unknown_options_and_a_second_root() {
+        assert_eq!(
+            parse_args(&["--nope"]).err().unwrap(),
+            "unknown option --nope"
+        );
+        assert_eq!(parse_args(&["-x"]).err().unwrap(), "unknown option -x");
+        assert!(parse_args(&["a", "b"])
+            .err()
+            .unwrap()
+            .contains("only one ROOT"));
+        //after -- a name starting with a dash is still the root
+        assert_eq!(
+            parse_args(&["--", "-site"]).unwrap().root,
+            PathBuf::from("-site")
+        );
+    }
+
+    #[test]
+    fn environment_only_fills_gaps() {
+        let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+        let vars = [
+            "PORT",
+            "MINI_SERVER_PORT",
+            "MINI_SERVER_SYMLINKS",
+            "MINI_SERVER_DENY",
+            "MINI_SERVER_ROOT",
+            "MINI_SERVER_HIDDEN",
+        ];
+
+        //invalid values are never looked at when the flag is there
+        env::set_var("MINI_SERVER_PORT", "abc");
+        env::set_var("MINI_SERVER_SYMLINKS", "bogus");
+        let config = run(&["--port", "3130", "--symlinks", "never"]).unwrap();
+        assert_eq!(config.port, 3130);
+        assert_eq!(config.symlinks, SymlinkPolicy::Never);
+        assert!(run(&["--port", "3130"])
+            .err()
+            .unwrap()
+            .starts_with("MINI_SERVER_SYMLINKS:"));
+        env::remove_var("MINI_SERVER_SYMLINKS");
+        assert!(run(&[]).err().unwrap().starts_with("MINI_SERVER_PORT:"));
+
+        //plain PORT still works, the prefixed name wins over it
+        env::remove_var("MINI_SERVER_PORT");
+        env::set_var("PORT", "4000");
+        assert_eq!(run(&[]).unwrap().port, 4000);
+        env::set_var("MINI_SERVER_PORT", "5000");
+        assert_eq!(run(&[]).unwrap().port, 5000);
+        assert_eq!(run(&["-p", "6000"]).unwrap().port, 6000);
+
+        env::set_var("MINI_SERVER_DENY", "*.key");
+        env::set_var("MINI_SERVER_ROOT", "/srv");
+        env::set_var("MINI_SERVER_HIDDEN", "1");
+        let config = run(&["--deny", ".env", "site"]).unwrap();
+        assert_eq!(config.deny, ["*.key", ".env"]);
+        assert_eq!(config.root, PathBuf::from("site"));
+        assert!(config.hidden);
+        assert_eq!(run(&[]).unwrap().root, PathBuf::from("/srv"));
+
+        for var in vars {
+            env::remove_var(var);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//validators and conditional requests as described in RFC 7232
+use chrono::{DateTime, Timelike, Utc};
+use hyper::{Body, Method, Request};
+use sha2::{Digest, Sha256};
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+pub struct Validators {
+    pub etag: String,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Proceed,
+    NotModified,
+    PreconditionFailed,
+}
+
+impl Validators {
+    //cheap validators that don't require reading the file
+    pub fn from_metadata(metadata: &Metadata) -> Validators {
+        let modified = metadata.modified().ok();
+        let mtime = modified
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_nanos());
+        Validators {
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), mtime),
+            //HTTP dates have whole second precision
+            last_modified: modified.and_then(|t| DateTime::<Utc>::from(t).with_nanosecond(0)),
+        }
+    }
+
+    //same as from_metadata but the tag is a hash of the contents
+    //so it survives touches and fresh checkouts
+    pub async fn from_contents(path: &Path, metadata: &Metadata) -> io::Result<Validators> {
+        let mut validators = Validators::from_metadata(metadata);
+        let mut file = File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        let digest: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        validators.etag = format!("\"{}\"", &digest[..32]);
+        Ok(validators)
+    }
+
+    pub fn last_modified_header(&self) -> Option<String> {
+        self.last_modified.map(http_date)
+    }
+
+    //decides what to do with If-Match, If-Unmodified-Since, If-None-Match
+    //and If-Modified-Since, evaluated in the order RFC 7232 section 6 gives
+    pub fn evaluate(&self, req: &Request<Body>) -> Outcome {
+        if let Some(value) = header(req, "if-match") {
+            if !self.matches(value, true) {
+                return Outcome::PreconditionFailed;
+            }
+        } else if let Some(since) = header(req, "if-unmodified-since").and_then(parse_http_date) {
+            if self.last_modified.is_none_or(|lm| lm > since) {
+                return Outcome::PreconditionFailed;
+            }
+        }
+        let safe = req.method() == Method::GET || req.method() == Method::HEAD;
+        if let Some(value) = header(req, "if-none-match") {
+            if self.matches(value, false) {
+                return if safe {
+                    Outcome::NotModified
+                } else {
+                    Outcome::PreconditionFailed
+                };
+            }
+        } else if let Some(since) = header(req, "if-modified-since").and_then(parse_http_date) {
+            if safe && self.last_modified.is_some_and(|lm| lm <= since) {
+                return Outcome::NotModified;
+            }
+        }
+        Outcome::Proceed
+    }
+
+    //If-Range only honours strong validators: an exact tag or an exact date
+    pub fn if_range(&self, req: &Request<Body>) -> bool {
+        match header(req, "if-range") {
+            None => true,
+            Some(value) if value.starts_with('"') => value == self.etag,
+            Some(value) if value.starts_with("W/") => false,
+            Some(value) => match (parse_http_date(value), self.last_modified) {
+                (Some(date), Some(lm)) => date == lm,
+                _ => false,
+            },
+        }
+    }
+
+    //compares against a list of entity tags, strong comparison rejects weak tags
+    fn matches(&self, list: &str, strong: bool) -> bool {
+        if list.trim() == "*" {
+            return true;
+        }
+        let ours = opaque(&self.etag);
+        list.split(',').map(str::trim).any(|tag| {
+            if strong && (tag.starts_with("W/") || self.etag.starts_with("W/")) {
+                false
+            } else {
+                opaque(tag) == ours
+            }
+        })
+    }
+}
+
+fn opaque(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn header<'a>(req: &'a Request<Body>, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+pub fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn validators() -> Validators {
+        Validators {
+            etag: String::from("\"abc\""),
+            last_modified: parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT"),
+        }
+    }
+
+    fn request(method: Method, headers: &[(&str, &str)]) -> Request<Body> {
+        let mut builder = Request::builder().method(method).uri("/file");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn evaluate(method: Method, headers: &[(&str, &str)]) -> Outcome {
+        validators().evaluate(&request(method, headers))
+    }
+
+    #[test]
+    fn parses_http_dates() {
+        let date = parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+        assert_eq!(date.timestamp(), 1445412480);
+        assert_eq!(http_date(date), "Wed, 21 Oct 2015 07:28:00 GMT");
+        assert_eq!(
+            parse_http_date(" Wed, 21 Oct 2015 09:28:00 +0200"),
+            Some(date)
+        );
+        assert_eq!(parse_http_date("yesterday"), None);
+        assert_eq!(parse_http_date(""), None);
+    }
+
+    #[test]
+    fn if_match_fails_preconditions() {
+        assert_eq!(
+            evaluate(Method::PUT, &[("if-match", "\"abc\"")]),
+            Outcome::Proceed
+        );
+        assert_eq!(
+            evaluate(Method::PUT, &[("if-match", "\"x\", \"abc\"")]),
+            Outcome::Proceed
+        );
+        assert_eq!(
+            evaluate(Method::PUT, &[("if-match", "*")]),
+            Outcome::Proceed
+        );
+        assert_eq!(
+            evaluate(Method::GET, &[("if-match", "\"other\"")]),
+            Outcome::PreconditionFailed
+        );
+        //If-Match uses the strong comparison
+        assert_eq!(
+            evaluate(Method::PUT, &[("if-match", "W/\"abc\"")]),
+            Outcome::PreconditionFailed
+        );
+        let weak = Validators {
+            etag: String::from("W/\"abc\""),
+            last_modified: None,
+        };
+        let req = request(Method::PUT, &[("if-match", "W/\"abc\"")]);
+        assert_eq!(weak.evaluate(&req), Outcome::PreconditionFailed);
+
+        let since = |date| evaluate(Method::PUT, &[("if-unmodified-since", date)]);
+        assert_eq!(since("Wed, 21 Oct 2015 07:28:00 GMT"), Outcome::Proceed);
+        assert_eq!(
+            since("Wed, 21 Oct 2015 07:27:59 GMT"),
+            Outcome::PreconditionFailed
+        );
+        //If-Match takes precedence over If-Unmodified-Since
+        assert_eq!(
+            evaluate(
+                Method::PUT,
+                &[
+                    ("if-match", "\"abc\""),
+                    ("if-unmodified-since", "Wed, 21 Oct 2015 07:27:59 GMT")
+                ]
+            ),
+            Outcome::Proceed
+        );
+    }
+
+    #[test]
+    fn if_none_match_depends_on_method() {
+        let tag = [("if-none-match", "\"abc\"")];
+        assert_eq!(evaluate(Method::GET, &tag), Outcome::NotModified);
+        assert_eq!(evaluate(Method::HEAD, &tag), Outcome::NotModified);
+        assert_eq!(evaluate(Method::PUT, &tag), Outcome::PreconditionFailed);
+        assert_eq!(
+            evaluate(Method::DELETE, &[("if-none-match", "*")]),
+            Outcome::PreconditionFailed
+        );
+        //If-None-Match uses the weak comparison
+        assert_eq!(
+            evaluate(Method::GET, &[("if-none-match", "W/\"abc\"")]),
+            Outcome::NotModified
+        );
+        assert_eq!(
+            evaluate(Method::GET, &[("if-none-match", "\"x\", \"y\"")]),
+            Outcome::Proceed
+        );
+
+        let since = |method, date| evaluate(method, &[("if-modified-since", date)]);
+        assert_eq!(
+            since(Method::GET, "Wed, 21 Oct 2015 07:28:00 GMT"),
+            Outcome::NotModified
+        );
+        assert_eq!(
+            since(Method::GET, "Wed, 21 Oct 2015 07:27:59 GMT"),
+            Outcome::Proceed
+        );
+        assert_eq!(
+            since(Method::PUT, "Wed, 21 Oct 2015 07:28:00 GMT"),
+            Outcome::Proceed
+        );
+        //a tag that doesn't match wins over a date that would
+        assert_eq!(
+            evaluate(
+                Method::GET,
+                &[
+                    ("if-none-match", "\"x\""),
+                    ("if-modified-since", "Wed, 21 Oct 2015 07:28:00 GMT")
+                ]
+            ),
+            Outcome::Proceed
+        );
+    }
+
+    #[test]
+    fn if_range_needs_strong_validators() {
+        let if_range = |value| validators().if_range(&request(Method::GET, &[("if-range", value)]));
+        assert!(validators().if_range(&request(Method::GET, &[])));
+        assert!(if_range("\"abc\""));
+        assert!(!if_range("\"other\""));
+        assert!(!if_range("W/\"abc\""));
+        assert!(if_range("Wed, 21 Oct 2015 07:28:00 GMT"));
+        //only the exact date, a later one could still describe an older version
+        assert!(!if_range("Wed, 21 Oct 2015 07:28:01 GMT"));
+        assert!(!if_range("not a date"));
+    }
+
+    #[test]
+    fn last_modified_has_whole_seconds() {
+        let path =
+            std::env::temp_dir().join(format!("mini-server-conditional-{}", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_millis(1_500))
+            .unwrap();
+        let validators = Validators::from_metadata(&file.metadata().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let header = validators.last_modified_header().unwrap();
+        assert_eq!(header, "Thu, 01 Jan 1970 00:00:01 GMT");
+        //so the date a client echoes back counts as not modified
+        let req = request(Method::GET, &[("if-modified-since", &header)]);
+        assert_eq!(validators.evaluate(&req), Outcome::NotModified);
+        assert!(validators.if_range(&request(Method::GET, &[("if-range", &header)])));
+    }
+}
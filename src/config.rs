@@ -0,0 +1,452 @@
+//settings shared by every request handler
+use crate::encoding;
+use crate::livereload::Reloader;
+use crate::mime;
+use crate::paths::{self, SymlinkPolicy};
+use async_compression::Level;
+use std::env;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub struct Config {
+    pub root: PathBuf,
+    //0 lets the OS pick a free port
+    pub port: u16,
+    //how many ports after port to try when it is taken
+    pub port_retries: u16,
+    //file the bound port is written to once listening
+    pub port_file: Option<PathBuf>,
+    //every address gets its own listener
+    pub bind: Vec<IpAddr>,
+    //PEM files, serve HTTPS when both are set
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    //generate a development CA and certificate instead of cert and key
+    pub self_signed: bool,
+    //where the generated certificates are kept between runs
+    pub self_signed_dir: Option<PathBuf>,
+    //plain HTTP port that redirects everything to HTTPS
+    pub redirect_http: Option<u16>,
+    //accept prior knowledge HTTP/2 on plain connections, TLS negotiates it with ALPN
+    pub h2c: bool,
+    //HTTP/2 tuning, None keeps hyper's defaults
+    pub h2_max_streams: Option<u32>,
+    pub h2_stream_window: Option<u32>,
+    pub h2_connection_window: Option<u32>,
+    //open the served url in a browser once listening
+    pub open: bool,
+    //no per request log lines
+    pub quiet: bool,
+    pub symlinks: SymlinkPolicy,
+    //list and serve names starting with a dot
+    pub hidden: bool,
+    //glob patterns for paths that are answered with 404
+    pub deny: Vec<String>,
+    //from a mime.types file and --mime, ahead of the built in table
+    pub mime_overrides: mime::Overrides,
+    //guess the type of extensionless files from their contents
+    pub sniff: bool,
+    //serve app.js.br and friends in place of app.js when the client accepts them
+    pub precompressed: bool,
+    //compress compressible responses for clients that accept it
+    pub compress: bool,
+    //files smaller than this are sent as they are
+    pub compress_min_size: u64,
+    //and so are files larger than this, compressing them would tie up a worker too long
+    pub compress_max_size: u64,
+    pub compress_level: Level,
+    //derive ETags from a hash of the contents instead of size and modification time
+    pub etag_hash: bool,
+    //served in place of the generated listing when a directory contains one of these
+    pub index_files: Vec<String>,
+    //answers page loads that would 404 with this file, for history API routing
+    pub spa_fallback: Option<String>,
+    //also use the fallback for missing paths that have an extension
+    pub spa_assets: bool,
+    //set with --live-reload, html pages get a script that reloads them on changes
+    pub live_reload: Option<Reloader>,
+    //how often live reload scans the root for changes
+    pub live_reload_interval: Duration,
+    //accept PUT and uploads from the listing
+    pub writable: bool,
+    //largest request body a write accepts
+    pub max_upload: u64,
+    //let writes replace existing files instead of answering 409
+    pub overwrite: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            root: PathBuf::from("."),
+            port: 3000,
+            port_retries: 0,
+            port_file: None,
+            bind: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            cert: None,
+            key: None,
+            self_signed: false,
+            self_signed_dir: None,
+            redirect_http: None,
+            h2c: false,
+            h2_max_streams: None,
+            h2_stream_window: None,
+            h2_connection_window: None,
+            open: false,
+            quiet: false,
+            symlinks: SymlinkPolicy::WithinRoot,
+            hidden: false,
+            deny: vec![],
+            mime_overrides: mime::Overrides::new(),
+            sniff: false,
+            precompressed: true,
+            compress: true,
+            compress_min_size: 1024,
+            compress_max_size: 8 << 20,
+            compress_level: Level::Default,
+            etag_hash: false,
+            index_files: vec![String::from("index.html"), String::from("index.htm")],
+            spa_fallback: None,
+            spa_assets: false,
+            live_reload: None,
+            live_reload_interval: Duration::from_millis(500),
+            writable: false,
+            max_upload: 100 << 20,
+            overwrite: false,
+        }
+    }
+}
+
+impl Config {
+    //environment variables are the fallback for anything not given on the command line,
+    //given lists the ones a flag already covered so they are neither read nor checked
+    pub fn apply_env(&mut self, given: &[&str]) -> Result<(), String> {
+        let env = Env { given };
+        let config = self;
+        if let Some(value) = env.var("ROOT") {
+            config.root = PathBuf::from(value);
+        }
+        //plain PORT is what hosting platforms set, it still works
+        if let Some((name, value)) = env.var_named("PORT").or_else(|| env.unprefixed("PORT")) {
+            config.port = parse_port(&value).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        if let Some(value) = env.var("PORT_RETRIES") {
+            config.port_retries =
+                parse_retries(&value).map_err(|e| format!("MINI_SERVER_PORT_RETRIES: {}", e))?;
+        }
+        if let Some(value) = env.var("PORT_FILE") {
+            config.port_file = Some(PathBuf::from(value)).filter(|p| !p.as_os_str().is_empty());
+        }
+        if let Some(value) = env.var("BIND") {
+            config.bind = parse_bind(&value).map_err(|e| format!("MINI_SERVER_BIND: {}", e))?;
+        }
+        if let Some(value) = env.var("TLS_CERT") {
+            config.cert = Some(PathBuf::from(value)).filter(|p| !p.as_os_str().is_empty());
+        }
+        if let Some(value) = env.var("TLS_KEY") {
+            config.key = Some(PathBuf::from(value)).filter(|p| !p.as_os_str().is_empty());
+        }
+        if let Some(value) = env.flag("SELF_SIGNED") {
+            config.self_signed = value;
+        }
+        if let Some(value) = env.var("SELF_SIGNED_DIR") {
+            config.self_signed_dir =
+                Some(PathBuf::from(value)).filter(|p| !p.as_os_str().is_empty());
+        }
+        if let Some(value) = env.var("REDIRECT_HTTP") {
+            config.redirect_http =
+                Some(parse_port(&value).map_err(|e| format!("MINI_SERVER_REDIRECT_HTTP: {}", e))?);
+        }
+        if let Some(value) = env.flag("H2C") {
+            config.h2c = value;
+        }
+        if let Some(value) = env.var("H2_MAX_STREAMS") {
+            config.h2_max_streams = Some(
+                parse_streams(&value).map_err(|e| format!("MINI_SERVER_H2_MAX_STREAMS: {}", e))?,
+            );
+        }
+        if let Some(value) = env.var("H2_STREAM_WINDOW") {
+            config.h2_stream_window = Some(
+                parse_window(&value).map_err(|e| format!("MINI_SERVER_H2_STREAM_WINDOW: {}", e))?,
+            );
+        }
+        if let Some(value) = env.var("H2_CONNECTION_WINDOW") {
+            config.h2_connection_window = Some(
+                parse_window(&value)
+                    .map_err(|e| format!("MINI_SERVER_H2_CONNECTION_WINDOW: {}", e))?,
+            );
+        }
+        if let Some(value) = env.flag("QUIET") {
+            config.quiet = value;
+        }
+        if let Some(value) = env.var("SYMLINKS") {
+            config.symlinks = value
+                .parse()
+                .map_err(|e| format!("MINI_SERVER_SYMLINKS: {}", e))?;
+        }
+        if let Some(value) = env.flag("HIDDEN") {
+            config.hidden = value;
+        }
+        if let Some(value) = env.var("DENY") {
+            config.deny = list(&value);
+        }
+        if let Some(value) = env.var("MIME_TYPES") {
+            config
+                .load_mime_types(Path::new(&value))
+                .map_err(|e| format!("MINI_SERVER_MIME_TYPES: {}", e))?;
+        }
+        if let Some(value) = env.flag("SNIFF") {
+            config.sniff = value;
+        }
+        if let Some(value) = env.flag("PRECOMPRESSED") {
+            config.precompressed = value;
+        }
+        if let Some(value) = env.flag("COMPRESS") {
+            config.compress = value;
+        }
+        if let Some(value) = env.var("COMPRESS_MIN_SIZE") {
+            config.compress_min_size =
+                parse_size(&value).map_err(|e| format!("MINI_SERVER_COMPRESS_MIN_SIZE: {}", e))?;
+        }
+        if let Some(value) = env.var("COMPRESS_MAX_SIZE") {
+            config.compress_max_size =
+                parse_size(&value).map_err(|e| format!("MINI_SERVER_COMPRESS_MAX_SIZE: {}", e))?;
+        }
+        if let Some(value) = env.var("COMPRESS_LEVEL") {
+            config.compress_level = encoding::parse_level(&value)
+                .map_err(|e| format!("MINI_SERVER_COMPRESS_LEVEL: {}", e))?;
+        }
+        if let Some(value) = env.flag("ETAG_HASH") {
+            config.etag_hash = value;
+        }
+        if let Some(value) = env.flag("WRITABLE") {
+            config.writable = value;
+        }
+        if let Some(value) = env.var("MAX_UPLOAD") {
+            config.max_upload =
+                parse_size(&value).map_err(|e| format!("MINI_SERVER_MAX_UPLOAD: {}", e))?;
+        }
+        if let Some(value) = env.flag("OVERWRITE") {
+            config.overwrite = value;
+        }
+        if let Some(value) = env.flag("LIVE_RELOAD") {
+            config.live_reload = Some(Reloader::default()).filter(|_| value);
+        }
+        if let Some(value) = env.var("LIVE_RELOAD_INTERVAL") {
+            config.live_reload_interval = parse_interval(&value)
+                .map_err(|e| format!("MINI_SERVER_LIVE_RELOAD_INTERVAL: {}", e))?;
+        }
+        if let Some(value) = env.var("INDEX_FILES") {
+            config.index_files = list(&value);
+        }
+        if let Some(value) = env.var("SPA_FALLBACK") {
+            config.spa_fallback = Some(value).filter(|v| !v.is_empty());
+        }
+        if let Some(value) = env.flag("SPA_FALLBACK_ASSETS") {
+            config.spa_assets = value;
+        }
+        Ok(())
+    }
+
+    //checks settings that only make sense together
+    pub fn validate(&self) -> Result<(), String> {
+        match (&self.cert, &self.key) {
+            (Some(_), None) => return Err(String::from("--cert needs --key as well")),
+            (None, Some(_)) => return Err(String::from("--key needs --cert as well")),
+            _ => {}
+        }
+        if self.self_signed && self.cert.is_some() {
+            return Err(String::from("--self-signed can't be combined with --cert"));
+        }
+        if self.redirect_http.is_some() && !self.tls() {
+            return Err(String::from("--redirect-http only makes sense with HTTPS"));
+        }
+        Ok(())
+    }
+
+    pub fn tls(&self) -> bool {
+        self.self_signed || (self.cert.is_some() && self.key.is_some())
+    }
+
+    pub fn load_mime_types(&mut self, path: &Path) -> Result<(), String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let entries =
+            mime::parse_mime_types(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.mime_overrides.extend(entries);
+        Ok(())
+    }
+
+    //whether path (below root) is kept out of listings and responses
+    //a hidden or denied directory hides everything inside it too
+    pub fn hides(&self, path: &Path) -> bool {
+        let relative = match path.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return false,
+        };
+        let dotfile = relative
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        if dotfile && !self.hidden {
+            return true;
+        }
+        relative.ancestors().any(|ancestor| {
+            !ancestor.as_os_str().is_empty()
+                && self
+                    .deny
+                    .iter()
+                    .any(|pattern| paths::glob(pattern, ancestor))
+        })
+    }
+
+    //hides, also applied to wherever links along path lead, so a link can't expose
+    //a file its real name would hide
+    pub async fn hides_real(&self, path: &Path) -> bool {
+        if self.hides(path) {
+            return true;
+        }
+        match paths::real(path).await {
+            Some(real) if real != path => self.hides(&real),
+            _ => false,
+        }
+    }
+}
+
+pub fn parse_port(value: &str) -> Result<u16, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid port number", value))
+}
+
+//a byte count, optionally with a k, m or g suffix
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let lower = value.trim().to_lowercase();
+    let (number, unit) = match lower.char_indices().find(|(_, c)| c.is_alphabetic()) {
+        Some((i, _)) => lower.split_at(i),
+        None => (lower.as_str(), ""),
+    };
+    let multiplier: u64 = match unit.trim_end_matches("ib").trim_end_matches('b') {
+        "" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        _ => return Err(format!("{:?} is not a valid size", value)),
+    };
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("{:?} is not a valid size", value))
+}
+
+pub fn parse_streams(value: &str) -> Result<u32, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid number of streams", value))
+}
+
+//HTTP/2 flow control windows top out at 2^31-1 bytes
+pub fn parse_window(value: &str) -> Result<u32, String> {
+    let size = parse_size(value)?;
+    match u32::try_from(size) {
+        Ok(size) if size < 1 << 31 => Ok(size),
+        _ => Err(format!(
+            "{:?} is over the 2GiB limit for HTTP/2 windows",
+            value
+        )),
+    }
+}
+
+//milliseconds, or seconds with an s suffix, e.g. 250 or 2s
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let lower = value.trim().to_lowercase();
+    let interval = match lower.strip_suffix("ms") {
+        Some(ms) => ms.trim().parse().ok().map(Duration::from_millis),
+        None => match lower.strip_suffix('s') {
+            Some(secs) => secs.trim().parse().ok().map(Duration::from_secs),
+            None => lower.parse().ok().map(Duration::from_millis),
+        },
+    };
+    interval
+        .filter(|interval| !interval.is_zero())
+        .ok_or_else(|| format!("{:?} is not a valid interval", value))
+}
+
+pub fn parse_retries(value: &str) -> Result<u16, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid number of retries", value))
+}
+
+//one or more comma separated addresses
+pub fn parse_bind(value: &str) -> Result<Vec<IpAddr>, String> {
+    list(value).iter().map(|v| parse_ip(v)).collect()
+}
+
+fn parse_ip(value: &str) -> Result<IpAddr, String> {
+    let value = value.trim();
+    //allow the bracketed form people copy out of urls
+    let unbracketed = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value);
+    unbracketed
+        .parse()
+        .map_err(|_| format!("{:?} is not a valid IP address", value))
+}
+
+//every variable is read as MINI_SERVER_NAME so unrelated ones can't change the server
+const PREFIX: &str = "MINI_SERVER_";
+
+struct Env<'a> {
+    given: &'a [&'a str],
+}
+
+impl Env<'_> {
+    fn var(&self, name: &str) -> Option<String> {
+        self.var_named(name).map(|(_, value)| value)
+    }
+
+    //the value together with the full variable name, for error messages
+    fn var_named(&self, name: &str) -> Option<(String, String)> {
+        let full = format!("{}{}", PREFIX, name);
+        self.lookup(name, full)
+    }
+
+    fn unprefixed(&self, name: &str) -> Option<(String, String)> {
+        self.lookup(name, name.to_string())
+    }
+
+    fn lookup(&self, name: &str, full: String) -> Option<(String, String)> {
+        if self.given.contains(&name) {
+            return None;
+        }
+        let value = env::var(&full).ok()?;
+        Some((full, value))
+    }
+
+    //boolean variables, anything but 0/false/no/off counts as on
+    fn flag(&self, name: &str) -> Option<bool> {
+        self.var(name).map(|val| {
+            !matches!(
+                val.trim().to_lowercase().as_str(),
+                "0" | "false" | "no" | "off"
+            )
+        })
+    }
+}
+
+//comma separated environment variables, empty items are dropped
+pub fn list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
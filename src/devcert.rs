@@ -0,0 +1,235 @@
+//a local CA and a leaf certificate for it, so --self-signed gives HTTPS without
+//reaching for mkcert or openssl
+use chrono::{Datelike, Duration, Utc};
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    KeyUsagePurpose, SanType,
+};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::CertificateDer;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const CA_NAME: &str = "mini-server development CA";
+//browsers refuse leaf certificates valid for more than 398 days
+const LEAF_DAYS: i64 = 365;
+//a cached leaf is replaced well before it expires
+const LEAF_REUSE_DAYS: u64 = 300;
+const CA_DAYS: i64 = 10 * 365;
+
+pub struct DevCert {
+    pub ca: PathBuf,
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    //SHA-256 of the CA certificate, for checking what got trusted
+    pub fingerprint: String,
+}
+
+//$XDG_CACHE_HOME/mini-server or the platform's equivalent
+pub fn default_dir() -> PathBuf {
+    let cache = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    cache.join("mini-server")
+}
+
+//the names the leaf is issued for: localhost plus every address of this machine
+pub fn local_names() -> Vec<String> {
+    let mut ips = vec![
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        IpAddr::V6(Ipv6Addr::LOCALHOST),
+    ];
+    if let Ok(interfaces) = if_addrs::get_if_addrs() {
+        ips.extend(interfaces.iter().map(|interface| interface.ip()));
+    }
+    let mut names: Vec<String> = ips.iter().map(IpAddr::to_string).collect();
+    names.sort();
+    names.dedup();
+    names.insert(0, String::from("localhost"));
+    names
+}
+
+//loads the CA from dir (creating it the first time) and makes sure there's a leaf
+//for names signed by it
+pub fn ensure(dir: &Path, names: &[String]) -> Result<DevCert, String> {
+    fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let ca_path = dir.join("ca.pem");
+    let ca_key_path = dir.join("ca-key.pem");
+    let (ca, issuer, ca_key) = match (read(&ca_path)?, read(&ca_key_path)?) {
+        (Some(ca), Some(key_pem)) => {
+            let ca_key = KeyPair::from_pem(&key_pem).map_err(|e| e.to_string())?;
+            //the same name and key give an equivalent issuer to sign with
+            let issuer = ca_params(None)
+                .self_signed(&ca_key)
+                .map_err(|e| e.to_string())?;
+            (ca, issuer, ca_key)
+        }
+        _ => {
+            let ca_key = KeyPair::generate().map_err(|e| e.to_string())?;
+            let issuer = ca_params(Some(CA_DAYS))
+                .self_signed(&ca_key)
+                .map_err(|e| e.to_string())?;
+            write(&ca_key_path, &ca_key.serialize_pem(), true)?;
+            write(&ca_path, &issuer.pem(), false)?;
+            (issuer.pem(), issuer, ca_key)
+        }
+    };
+
+    let cert_path = dir.join("cert.pem");
+    let key_path = dir.join("key.pem");
+    let names_path = dir.join("names");
+    let wanted = names.join("\n");
+    let fresh = read(&names_path)?.as_deref() == Some(wanted.as_str())
+        && key_path.exists()
+        && age_days(&cert_path).is_some_and(|days| days < LEAF_REUSE_DAYS)
+        && modified(&cert_path) >= modified(&ca_path);
+    if !fresh {
+        let key = KeyPair::generate().map_err(|e| e.to_string())?;
+        let cert = leaf_params(names)?
+            .signed_by(&key, &issuer, &ca_key)
+            .map_err(|e| e.to_string())?;
+        write(&key_path, &key.serialize_pem(), true)?;
+        //the chain, so clients that only trust the CA can verify it
+        write(&cert_path, &format!("{}{}", cert.pem(), ca), false)?;
+        write(&names_path, &wanted, false)?;
+    }
+
+    Ok(DevCert {
+        fingerprint: fingerprint(&ca)?,
+        ca: ca_path,
+        cert: cert_path,
+        key: key_path,
+    })
+}
+
+//days is None when only rebuilding the issuer of an existing CA
+fn ca_params(days: Option<i64>) -> CertificateParams {
+    let mut params = CertificateParams::default();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params.distinguished_name.push(DnType::CommonName, CA_NAME);
+    params
+        .distinguished_name
+        .push(DnType::OrganizationName, "mini-server");
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    if let Some(days) = days {
+        validity(&mut params, days);
+    }
+    params
+}
+
+fn leaf_params(names: &[String]) -> Result<CertificateParams, String> {
+    let mut params = CertificateParams::default();
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "localhost");
+    for name in names.iter() {
+        params.subject_alt_names.push(match name.parse::<IpAddr>() {
+            Ok(ip) => SanType::IpAddress(ip),
+            Err(_) => SanType::DnsName(name.as_str().try_into().map_err(|e| format!("{}", e))?),
+        });
+    }
+    params.key_usages = vec![
+        KeyUsagePurpose::DigitalSignature,
+        KeyUsagePurpose::KeyEncipherment,
+    ];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    params.use_authority_key_identifier_extension = true;
+    validity(&mut params, LEAF_DAYS);
+    Ok(params)
+}
+
+//starts a day early so clocks that are a little behind still accept it
+fn validity(params: &mut CertificateParams, days: i64) {
+    let date = |offset: i64| {
+        let day = Utc::now() + Duration::days(offset);
+        rcgen::date_time_ymd(day.year(), day.month() as u8, day.day() as u8)
+    };
+    params.not_before = date(-1);
+    params.not_after = date(days);
+}
+
+//uppercase hex pairs separated by colons, the way browsers and openssl show it
+fn fingerprint(ca_pem: &str) -> Result<String, String> {
+    let der = CertificateDer::from_pem_slice(ca_pem.as_bytes()).map_err(|e| e.to_string())?;
+    let digest = Sha256::digest(der.as_ref());
+    Ok(digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":"))
+}
+
+fn read(path: &Path) -> Result<Option<String>, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+//keys are only readable by their owner
+fn write(path: &Path, contents: &str, private: bool) -> Result<(), String> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn age_days(path: &Path) -> Option<u64> {
+    let age = SystemTime::now().duration_since(modified(path)?).ok()?;
+    Some(age.as_secs() / (24 * 60 * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issues_a_usable_chain() {
+        let dir = std::env::temp_dir().join(format!("mini-server-devcert-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let names = vec![String::from("localhost"), String::from("127.0.0.1")];
+        let first = ensure(&dir, &names).unwrap();
+        let pem = fs::read(&first.cert).unwrap();
+        let key = fs::read(&first.key).unwrap();
+        assert!(crate::tls::server_config(&pem, &key).is_ok());
+        assert_eq!(first.fingerprint.len(), 32 * 3 - 1);
+
+        //a second run reuses both, new names only replace the leaf
+        let second = ensure(&dir, &names).unwrap();
+        assert_eq!(second.fingerprint, first.fingerprint);
+        assert_eq!(fs::read(&second.cert).unwrap(), pem);
+        let names = vec![String::from("localhost"), String::from("10.0.0.2")];
+        let third = ensure(&dir, &names).unwrap();
+        assert_eq!(third.fingerprint, first.fingerprint);
+        assert_ne!(fs::read(&third.cert).unwrap(), pem);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,204 @@
+//content codings: Accept-Encoding negotiation and on the fly compression
+use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZlibEncoder, ZstdEncoder};
+use async_compression::Level;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, BufReader};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+    Deflate,
+}
+
+//in order of preference when the client likes several equally
+pub const ALL: [Encoding; 4] = [
+    Encoding::Brotli,
+    Encoding::Zstd,
+    Encoding::Gzip,
+    Encoding::Deflate,
+];
+
+impl Encoding {
+    //the token used in Accept-Encoding and Content-Encoding
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    //where a build step would have put a compressed copy of path
+    pub fn sibling(self, path: &Path) -> Option<PathBuf> {
+        let extension = match self {
+            Encoding::Brotli => ".br",
+            Encoding::Zstd => ".zst",
+            Encoding::Gzip => ".gz",
+            Encoding::Deflate => return None,
+        };
+        let mut sibling = path.as_os_str().to_owned();
+        sibling.push(extension);
+        Some(PathBuf::from(sibling))
+    }
+
+    //the same representation compressed is a different one, so it needs its own tag
+    pub fn tag(self, etag: &str) -> String {
+        match etag.strip_suffix('"') {
+            Some(start) => format!("{}-{}\"", start, self.name()),
+            None => format!("{}-{}", etag, self.name()),
+        }
+    }
+}
+
+//picks the coding from supported the client rates highest, None means identity
+pub fn negotiate(accept: &str, supported: &[Encoding]) -> Option<Encoding> {
+    let mut ratings: Vec<(&str, f32)> = vec![];
+    for item in accept.split(',') {
+        let mut params = item.split(';');
+        let name = params.next().unwrap_or("").trim();
+        if name.is_empty() {
+            continue;
+        }
+        let q = params
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        ratings.push((name, q));
+    }
+    let rating = |encoding: Encoding| -> f32 {
+        let named = ratings
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(encoding.name()))
+            //x-gzip is an old alias still sent by some clients
+            .or_else(|| match encoding {
+                Encoding::Gzip => ratings
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("x-gzip")),
+                _ => None,
+            });
+        match named {
+            Some((_, q)) => *q,
+            None => ratings
+                .iter()
+                .find(|(name, _)| *name == "*")
+                .map_or(0.0, |(_, q)| *q),
+        }
+    };
+    let mut best: Option<(Encoding, f32)> = None;
+    for encoding in supported.iter() {
+        let q = rating(*encoding);
+        if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+            best = Some((*encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+//types that are worth compressing, already compressed media and archives are not
+pub fn compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.starts_with("text/")
+        || mime.ends_with("+json")
+        || mime.ends_with("+xml")
+        || matches!(
+            mime,
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/toml"
+                | "application/yaml"
+                | "application/rtf"
+                | "application/vnd.ms-fontobject"
+                | "font/ttf"
+                | "font/otf"
+                | "image/bmp"
+                | "image/vnd.microsoft.icon"
+        )
+}
+
+pub fn parse_level(value: &str) -> Result<Level, String> {
+    match value.trim().to_lowercase().as_str() {
+        "fastest" => Ok(Level::Fastest),
+        "default" => Ok(Level::Default),
+        "best" => Ok(Level::Best),
+        number => number
+            .parse()
+            .map(Level::Precise)
+            .map_err(|_| format!("{:?} is not fastest, default, best or a number", value)),
+    }
+}
+
+//default means cheap enough to do for every request, not the codec's own default
+//which for brotli is its slowest setting
+pub fn on_the_fly(encoding: Encoding, level: Level) -> Level {
+    match level {
+        Level::Default => Level::Precise(match encoding {
+            Encoding::Brotli => 4,
+            Encoding::Zstd => 3,
+            Encoding::Gzip | Encoding::Deflate => 6,
+        }),
+        level => level,
+    }
+}
+
+pub fn compress<R>(reader: R, encoding: Encoding, level: Level) -> Box<dyn AsyncRead + Send + Unpin>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let reader = BufReader::new(reader);
+    let level = on_the_fly(encoding, level);
+    match encoding {
+        Encoding::Brotli => Box::new(BrotliEncoder::with_quality(reader, level)),
+        Encoding::Zstd => Box::new(ZstdEncoder::with_quality(reader, level)),
+        Encoding::Gzip => Box::new(GzipEncoder::with_quality(reader, level)),
+        Encoding::Deflate => Box::new(ZlibEncoder::with_quality(reader, level)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negotiates_encodings() {
+        assert_eq!(negotiate("gzip, deflate, br", &ALL), Some(Encoding::Brotli));
+        assert_eq!(negotiate("gzip, deflate", &ALL), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0.5, gzip", &ALL), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br;q=0, *", &ALL), Some(Encoding::Zstd));
+        assert_eq!(negotiate("x-gzip", &ALL), Some(Encoding::Gzip));
+        assert_eq!(negotiate("identity", &ALL), None);
+        assert_eq!(negotiate("", &ALL), None);
+        assert_eq!(negotiate("br", &[Encoding::Gzip]), None);
+    }
+
+    #[test]
+    fn tags_per_encoding() {
+        assert_eq!(Encoding::Gzip.tag("\"abc\""), "\"abc-gzip\"");
+        assert_eq!(Encoding::Brotli.tag("W/\"abc\""), "W/\"abc-br\"");
+    }
+
+    #[test]
+    fn default_level_is_cheap() {
+        let level = |encoding| format!("{:?}", on_the_fly(encoding, Level::Default));
+        assert_eq!(level(Encoding::Brotli), "Precise(4)");
+        assert_eq!(level(Encoding::Zstd), "Precise(3)");
+        assert_eq!(level(Encoding::Gzip), "Precise(6)");
+        assert_eq!(
+            format!("{:?}", on_the_fly(Encoding::Brotli, Level::Best)),
+            "Best"
+        );
+    }
+
+    #[test]
+    fn compresses_text_only() {
+        assert!(compressible("text/css; charset=utf-8"));
+        assert!(compressible("application/wasm"));
+        assert!(compressible("image/svg+xml; charset=utf-8"));
+        assert!(!compressible("image/png"));
+        assert!(!compressible("application/zip"));
+    }
+}
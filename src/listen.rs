@@ -0,0 +1,104 @@
+//opening listening sockets and working out where they can be reached
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+
+pub struct Listener {
+    pub listener: TcpListener,
+    //the address actually bound, with the real port when 0 was asked for
+    pub addr: SocketAddr,
+    pub dual_stack: bool,
+}
+
+//binds every ip on the same port, moving on to the next port up to retries times
+//when one of them is taken
+//errors name the ports that were tried, with retries that's a range
+pub fn bind_all(ips: &[IpAddr], port: u16, retries: u16) -> io::Result<Vec<Listener>> {
+    let mut attempt = 0;
+    loop {
+        let candidate = if port == 0 {
+            0
+        } else {
+            match port.checked_add(attempt) {
+                Some(candidate) => candidate,
+                None => return Err(tried(port, u16::MAX, io::ErrorKind::AddrInUse.into())),
+            }
+        };
+        match bind_port(ips, candidate) {
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse && attempt < retries => attempt += 1,
+            Err(e) => return Err(tried(port, candidate, e)),
+            result => return result,
+        }
+    }
+}
+
+fn tried(first: u16, last: u16, e: io::Error) -> io::Error {
+    let ports = if first == last {
+        format!("port {}", first)
+    } else {
+        format!("ports {}-{}", first, last)
+    };
+    io::Error::new(e.kind(), format!("{}: {}", ports, e))
+}
+
+fn bind_port(ips: &[IpAddr], mut port: u16) -> io::Result<Vec<Listener>> {
+    let has_ipv4 = ips.iter().any(|ip| ip.is_ipv4());
+    let mut listeners = vec![];
+    for ip in ips.iter() {
+        //:: takes IPv4 as well unless an IPv4 address was asked for separately
+        let dual_stack = ip.is_ipv6() && ip.is_unspecified() && !has_ipv4;
+        let listener = bind(SocketAddr::new(*ip, port), !dual_stack)?;
+        let addr = listener.local_addr()?;
+        //with port 0 the first socket picks the port for all the others
+        port = addr.port();
+        listeners.push(Listener {
+            listener,
+            addr,
+            dual_stack,
+        });
+    }
+    Ok(listeners)
+}
+
+//v6_only=false on an unspecified IPv6 address gives one socket for both families
+fn bind(addr: SocketAddr, v6_only: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    //lets a restarted server reuse the port straight away
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+//urls a browser can use to reach addr, unspecified addresses expand to every interface
+pub fn urls(scheme: &str, addr: SocketAddr, dual_stack: bool) -> Vec<String> {
+    if !addr.ip().is_unspecified() {
+        return vec![url(scheme, addr)];
+    }
+    let interfaces = match if_addrs::get_if_addrs() {
+        Ok(interfaces) => interfaces,
+        Err(_) => return vec![url(scheme, addr)],
+    };
+    let mut urls: Vec<String> = interfaces
+        .iter()
+        .map(|interface| interface.ip())
+        .filter(|ip| match ip {
+            IpAddr::V4(_) => addr.is_ipv4() || dual_stack,
+            //link local addresses need a zone id which browsers don't handle well
+            IpAddr::V6(v6) => addr.is_ipv6() && (v6.segments()[0] & 0xffc0) != 0xfe80,
+        })
+        .map(|ip| url(scheme, SocketAddr::new(ip, addr.port())))
+        .collect();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+pub fn url(scheme: &str, addr: SocketAddr) -> String {
+    format!("{}://{}/", scheme, addr)
+}
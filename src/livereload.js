@@ -0,0 +1,30 @@
+//injected by mini-server --live-reload: reloads the page when files change and
+//swaps stylesheets in place when only css did
+(function () {
+  var opened = false;
+  var source = new EventSource("/__livereload");
+  source.onopen = function () {
+    //the server restarted, whatever changed meanwhile was missed
+    if (opened) {
+      location.reload();
+    }
+    opened = true;
+  };
+  source.onmessage = function (event) {
+    if (event.data !== "css") {
+      location.reload();
+      return;
+    }
+    document.querySelectorAll('link[rel="stylesheet"]').forEach(function (link) {
+      var url = new URL(link.href);
+      url.searchParams.set("livereload", Date.now());
+      //the old sheet stays until the new one is in, so nothing flashes unstyled
+      var fresh = link.cloneNode();
+      fresh.href = url.href;
+      fresh.onload = fresh.onerror = function () {
+        link.remove();
+      };
+      link.after(fresh);
+    });
+  };
+})();
@@ -0,0 +1,247 @@
+//--live-reload: watches the root and tells open pages over server-sent events
+use crate::config::Config;
+use futures_util::stream::{self, StreamExt};
+use hyper::body::Bytes;
+use hyper::{Body, Response};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+//where pages subscribe to changes and fetch the script from
+pub const EVENTS: &str = "/__livereload";
+pub const SCRIPT: &str = "/__livereload.js";
+
+const SCRIPT_SOURCE: &str = include_str!("livereload.js");
+const TAG: &[u8] = b"<script src=\"/__livereload.js\"></script>";
+
+//comments sent on idle connections so proxies don't time them out
+const KEEPALIVE: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Change {
+    //only stylesheets changed, they can be swapped without a reload
+    Css,
+    Page,
+}
+
+impl Change {
+    fn name(self) -> &'static str {
+        match self {
+            Change::Css => "css",
+            Change::Page => "reload",
+        }
+    }
+}
+
+//fans changes out to every open page
+#[derive(Clone)]
+pub struct Reloader {
+    changes: broadcast::Sender<Change>,
+}
+
+impl Default for Reloader {
+    fn default() -> Reloader {
+        let (changes, _) = broadcast::channel(16);
+        Reloader { changes }
+    }
+}
+
+impl Reloader {
+    //a text/event-stream that lasts as long as the page is open
+    pub fn events(&self) -> Response<Body> {
+        let receiver = self.changes.subscribe();
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            let event = match tokio::time::timeout(KEEPALIVE, receiver.recv()).await {
+                Ok(Ok(change)) => format!("data: {}\n\n", change.name()),
+                //missed some, a reload covers whatever they were
+                Ok(Err(RecvError::Lagged(_))) => format!("data: {}\n\n", Change::Page.name()),
+                Ok(Err(RecvError::Closed)) => return None,
+                Err(_) => String::from(": keepalive\n\n"),
+            };
+            Some((Ok::<_, io::Error>(Bytes::from(event)), receiver))
+        });
+        let start = stream::once(async { Ok(Bytes::from("retry: 1000\n\n")) });
+        Response::builder()
+            .status(200)
+            .header("Content-type", "text/event-stream")
+            .header("Cache-Control", "no-store")
+            .body(Body::wrap_stream(start.chain(events)))
+            .unwrap()
+    }
+}
+
+pub fn script() -> Response<Body> {
+    Response::builder()
+        .status(200)
+        .header("Content-type", "text/javascript; charset=utf-8")
+        .header("Cache-Control", "no-store")
+        .body(SCRIPT_SOURCE.into())
+        .unwrap()
+}
+
+//adds the script tag before the closing body tag, or at the end if there is none
+pub fn inject(html: &[u8]) -> Vec<u8> {
+    let lower = html.to_ascii_lowercase();
+    let at = lower
+        .windows(7)
+        .rposition(|w| w == b"</body>")
+        .unwrap_or(html.len());
+    let mut page = Vec::with_capacity(html.len() + TAG.len());
+    page.extend_from_slice(&html[..at]);
+    page.extend_from_slice(TAG);
+    page.extend_from_slice(&html[at..]);
+    page
+}
+
+type Snapshot = HashMap<PathBuf, (Option<SystemTime>, u64)>;
+
+//polls the root every live_reload_interval, which is also how long bursts of writes
+//get to settle, and broadcasts a change whenever something visible was added,
+//removed or modified
+pub fn watch(config: Arc<Config>) {
+    let reloader = match &config.live_reload {
+        Some(reloader) => reloader.clone(),
+        None => return,
+    };
+    tokio::spawn(async move {
+        let mut previous: Option<Snapshot> = None;
+        let mut interval = tokio::time::interval(config.live_reload_interval);
+        loop {
+            interval.tick().await;
+            let scan = config.clone();
+            let current = match tokio::task::spawn_blocking(move || snapshot(&scan)).await {
+                Ok(current) => current,
+                Err(_) => continue,
+            };
+            if let Some(change) = previous.as_ref().and_then(|p| classify(p, &current)) {
+                //nobody listening is fine
+                let _ = reloader.changes.send(change);
+            }
+            previous = Some(current);
+        }
+    });
+}
+
+fn snapshot(config: &Config) -> Snapshot {
+    let mut files = Snapshot::new();
+    let mut pending = vec![config.root.clone()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            //hidden and denied directories are never entered, so a big node_modules
+            //or .git costs one check per scan instead of one per file inside
+            if config.hides(&path) {
+                continue;
+            }
+            //links aren't followed, they could lead in circles
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => pending.push(path),
+                Ok(_) => {
+                    if let Ok(metadata) = entry.metadata() {
+                        files.insert(path, (metadata.modified().ok(), metadata.len()));
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+    }
+    files
+}
+
+fn classify(previous: &Snapshot, current: &Snapshot) -> Option<Change> {
+    let changed: Vec<&Path> = current
+        .iter()
+        .filter(|(path, stamp)| previous.get(*path) != Some(stamp))
+        .map(|(path, _)| path.as_path())
+        .chain(
+            previous
+                .keys()
+                .filter(|path| !current.contains_key(*path))
+                .map(PathBuf::as_path),
+        )
+        .collect();
+    if changed.is_empty() {
+        None
+    } else if changed
+        .iter()
+        .all(|path| path.extension().is_some_and(|ext| ext == "css"))
+    {
+        Some(Change::Css)
+    } else {
+        Some(Change::Page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn injects_before_closing_body() {
+        assert_eq!(
+            inject(b"<html><body><p>hi</p></BODY></html>"),
+            b"<html><body><p>hi</p><script src=\"/__livereload.js\"></script></BODY></html>"
+        );
+        assert_eq!(
+            inject(b"<p>fragment</p>"),
+            b"<p>fragment</p><script src=\"/__livereload.js\"></script>"
+        );
+    }
+
+    #[test]
+    fn css_changes_swap_stylesheets() {
+        let stamp = (Some(SystemTime::UNIX_EPOCH), 1);
+        let newer = (Some(SystemTime::now()), 1);
+        let before: Snapshot = [
+            (PathBuf::from("/r/index.html"), stamp),
+            (PathBuf::from("/r/site.css"), stamp),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(classify(&before, &before), None);
+
+        let mut after = before.clone();
+        after.insert(PathBuf::from("/r/site.css"), newer);
+        assert_eq!(classify(&before, &after), Some(Change::Css));
+
+        after.insert(PathBuf::from("/r/index.html"), newer);
+        assert_eq!(classify(&before, &after), Some(Change::Page));
+
+        let mut removed = before.clone();
+        removed.remove(Path::new("/r/index.html"));
+        assert_eq!(classify(&before, &removed), Some(Change::Page));
+    }
+
+    #[test]
+    fn skips_hidden_and_denied_directories() {
+        let root =
+            std::env::temp_dir().join(format!("mini-server-livereload-{}", std::process::id()));
+        for dir in ["site", "node_modules/pkg", ".git"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "index.html",
+            "site/app.css",
+            "node_modules/pkg/index.js",
+            ".git/HEAD",
+        ] {
+            std::fs::write(root.join(file), "x").unwrap();
+        }
+        let config = Config {
+            root: root.clone(),
+            deny: vec![String::from("node_modules/**")],
+            ..Config::default()
+        };
+        let mut seen: Vec<PathBuf> = snapshot(&config).into_keys().collect();
+        seen.sort();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(seen, [root.join("index.html"), root.join("site/app.css")]);
+    }
+}
@@ -1,10 +1,41 @@
+mod cli;
+mod conditional;
+mod config;
+mod devcert;
+mod encoding;
+mod listen;
+mod livereload;
+mod mime;
+mod paths;
+mod range;
+mod tls;
+mod write;
+
 use chrono::{DateTime, Utc};
+use cli::Action;
+use conditional::{Outcome, Validators};
+use config::Config;
+use encoding::Encoding;
+use futures_util::future::{self, BoxFuture, FutureExt};
+use futures_util::stream::{self, BoxStream, StreamExt};
+use hyper::body::Bytes;
+use hyper::http::uri::Authority;
+use hyper::server::{self, accept};
 use hyper::service::{make_service_fn, service_fn};
-use hyper::{Body, Request, Response, Server};
-use std::{convert::Infallible, env, io, net::SocketAddr};
+use hyper::{http::response, Body, Method, Request, Response, Server};
+use paths::PathError;
+use percent_encoding::percent_decode_str;
+use range::Ranges;
+use std::io::SeekFrom;
+use std::net::TcpListener;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::{convert::Infallible, env, fs::Metadata, io, process};
 use tokio::fs;
 use tokio::fs::File;
-use tokio::io::AsyncReadExt; // for read_to_end()
+use tokio::io::{AsyncReadExt, AsyncSeekExt}; // for take() and seek()
+use tokio_util::io::ReaderStream;
 
 fn not_found() -> Response<Body> {
     Response::builder()
@@ -20,6 +51,50 @@ fn forbidden() -> Response<Body> {
         .unwrap()
 }
 
+fn bad_request() -> Response<Body> {
+    Response::builder()
+        .status(400)
+        .body("bad request\r\n".into())
+        .unwrap()
+}
+
+fn allowed(config: &Config) -> &'static str {
+    if config.writable {
+        "GET, HEAD, OPTIONS, PUT, POST, DELETE, MKCOL, MOVE"
+    } else {
+        "GET, HEAD, OPTIONS"
+    }
+}
+
+fn method_not_allowed(config: &Config) -> Response<Body> {
+    Response::builder()
+        .status(405)
+        .header("Allow", allowed(config))
+        .body("method not allowed\r\n".into())
+        .unwrap()
+}
+
+fn conflict() -> Response<Body> {
+    Response::builder()
+        .status(409)
+        .body("conflict\r\n".into())
+        .unwrap()
+}
+
+fn precondition_failed() -> Response<Body> {
+    Response::builder()
+        .status(412)
+        .body("precondition failed\r\n".into())
+        .unwrap()
+}
+
+fn too_large() -> Response<Body> {
+    Response::builder()
+        .status(413)
+        .body("too large\r\n".into())
+        .unwrap()
+}
+
 fn trouble() -> Response<Body> {
     Response::builder()
         .status(500)
@@ -27,15 +102,46 @@ fn trouble() -> Response<Body> {
         .unwrap()
 }
 
-async fn files(path: &str) -> io::Result<Vec<String>> {
+struct Entry {
+    name: String,
+    is_dir: bool,
+    //where a symlink points, for links the policy lets us follow
+    link: Option<String>,
+}
+
+async fn files(config: &Config, path: &Path) -> io::Result<Vec<Entry>> {
     let mut file_names = vec![];
     let mut entries = fs::read_dir(path).await?;
 
     while let Some(entry) = entries.next_entry().await? {
-        if let Ok(metadata) = entry.metadata().await {
-            if metadata.is_file() {
+        if config.hides_real(&entry.path()).await {
+            continue;
+        }
+        //entry.metadata() describes the link itself, not what it points to
+        let mut link = None;
+        let mut metadata = entry.metadata().await;
+        if metadata.as_ref().is_ok_and(|m| m.file_type().is_symlink()) {
+            let path = entry.path();
+            if paths::check(&config.root, config.symlinks, &path)
+                .await
+                .is_err()
+            {
+                continue;
+            }
+            link = fs::read_link(&path)
+                .await
+                .ok()
+                .map(|target| target.display().to_string());
+            metadata = fs::metadata(&path).await;
+        }
+        if let Ok(metadata) = metadata {
+            if metadata.is_file() || metadata.is_dir() {
                 if let Ok(name) = entry.file_name().into_string() {
-                    file_names.push(name);
+                    file_names.push(Entry {
+                        name,
+                        is_dir: metadata.is_dir(),
+                        link,
+                    });
                 }
             }
         } else {
@@ -43,90 +149,506 @@ async fn files(path: &str) -> io::Result<Vec<String>> {
         }
     }
 
+    //directories first, then alphabetical
+    file_names.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
     Ok(file_names)
 }
 
-async fn index_view(_req: &Request<Body>) -> Response<Body> {
-    let mut contents = String::from(
+//listings load nothing from elsewhere and run nothing of their own, live reload's
+//script and the upload form open up just what they need
+fn listing_csp(config: &Config) -> String {
+    let form_action = if config.writable { "'self'" } else { "'none'" };
+    let mut csp = format!(
+        "default-src 'none'; img-src 'self'; base-uri 'none'; form-action {}; frame-ancestors 'none'",
+        form_action
+    );
+    if config.live_reload.is_some() {
+        csp.push_str("; script-src 'self'; connect-src 'self'");
+    }
+    csp
+}
+
+async fn index_view(req: &Request<Body>, config: &Config, dir: &Path) -> Response<Body> {
+    let url_path = req.uri().path();
+    //shown decoded, so it has to be escaped like any other text
+    let heading = html_escape(&percent_decode_str(url_path).decode_utf8_lossy());
+    let mut contents = format!(
         "
 <!DOCTYPE html>
 <html lang=\"en\">
   <head>
     <meta charset=\"UTF-8\">
-    <title>index</title>
+    <title>index of {}</title>
   </head>
   <body>
-    <h3>Welcome</h3>
+    <h3>{}</h3>
 <ul>
 ",
+        heading, heading
     );
-    if let Ok(fnames) = files(".").await {
-        for fname in fnames.iter() {
-            let chunk = format!("<li><a href=\"{}\">{}</a></li>", fname, fname);
+    if url_path != "/" {
+        contents.push_str("<li><a href=\"../\">..</a></li>");
+    }
+    if let Ok(entries) = files(config, dir).await {
+        for entry in entries.iter() {
+            let slash = if entry.is_dir { "/" } else { "" };
+            //the encoded href is escaped as well in case the encode set ever loosens
+            let target = match &entry.link {
+                Some(link) => format!(" &rarr; {}", html_escape(link)),
+                None => String::new(),
+            };
+            let chunk = format!(
+                "<li><a href=\"{}{}\">{}{}</a>{}</li>",
+                html_escape(&paths::encode_segment(&entry.name)),
+                slash,
+                html_escape(&entry.name),
+                slash,
+                target
+            );
             contents.push_str(&chunk);
         }
     }
-    contents.push_str("</ul></body></html>");
+    contents.push_str("</ul>");
+    if config.writable {
+        //posts back to this same url
+        contents.push_str(
+            "<form method=\"post\" enctype=\"multipart/form-data\">\
+             <input type=\"file\" name=\"file\" multiple required> \
+             <button>upload</button></form>",
+        );
+    }
+    contents.push_str("</body></html>");
+    if config.live_reload.is_some() {
+        contents = String::from_utf8_lossy(&livereload::inject(contents.as_bytes())).into();
+    }
     Response::builder()
         .status(200)
-        .header("Content-type", "text/html")
+        .header("Content-type", "text/html; charset=utf-8")
+        .header("Content-Security-Policy", listing_csp(config))
+        .header("X-Content-Type-Options", "nosniff")
         .body(contents.into())
         .unwrap()
 }
 
-async fn file_view(req: &Request<Body>) -> Response<Body> {
-    let mut chars = req.uri().path().chars();
-    chars.next(); //drop / which is first character in path
-    let path = chars.as_str();
-    //first check for dots
-    //this may be unnecessary - hyper seems to always flatten to /
-    if path.contains("..") {
-        forbidden()
+fn html_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+//directories are only listed under a trailing slash so relative links resolve inside them,
+//the location is rebuilt from the normalized path, a raw //assets would send
+//browsers off to a host called assets
+fn add_slash(req: &Request<Body>) -> Response<Body> {
+    let path: String = match paths::normalize(req.uri().path()) {
+        Ok(relative) => relative
+            .iter()
+            .map(|segment| format!("/{}", paths::encode_segment(&segment.to_string_lossy())))
+            .collect(),
+        Err(e) => return rejected(e),
+    };
+    let location = match req.uri().query() {
+        Some(query) => format!("{}/?{}", path, query),
+        None => format!("{}/", path),
+    };
+    Response::builder()
+        .status(301)
+        .header("Location", location)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn file_view(req: &Request<Body>, config: &Config) -> Response<Body> {
+    if let Some(reloader) = &config.live_reload {
+        match req.uri().path() {
+            livereload::EVENTS => return reloader.events(),
+            livereload::SCRIPT => return livereload::script(),
+            _ => {}
+        }
+    }
+    let path = match paths::resolve(&config.root, config.symlinks, req.uri().path()).await {
+        Ok(path) => path,
+        Err(e) => return rejected(e),
+    };
+    //404 rather than 403 so nobody learns the file exists
+    if config.hides_real(&path).await {
+        return not_found();
+    }
+    match fs::metadata(&path).await {
+        Ok(metadata) if metadata.is_dir() => {
+            if !req.uri().path().ends_with('/') {
+                add_slash(req)
+            } else if let Some(index) = index_file(config, &path).await {
+                serve_file(req, config, &index).await
+            } else {
+                index_view(req, config, &path).await
+            }
+        }
+        Ok(_) => serve_file(req, config, &path).await,
+        Err(_) => match &config.spa_fallback {
+            Some(fallback) if wants_spa_fallback(req, config) => {
+                match paths::resolve(&config.root, config.symlinks, fallback).await {
+                    Ok(fallback) => serve_file(req, config, &fallback).await,
+                    Err(_) => forbidden(),
+                }
+            }
+            _ => not_found(),
+        },
+    }
+}
+
+//the answer for a url path that doesn't map onto the root
+fn rejected(e: PathError) -> Response<Body> {
+    match e {
+        PathError::Escapes => forbidden(),
+        PathError::Invalid => bad_request(),
+        PathError::Symlink => not_found(),
+    }
+}
+
+//client side routes are page loads for paths that don't exist on disk
+fn wants_spa_fallback(req: &Request<Body>, config: &Config) -> bool {
+    let accepts_html = req
+        .headers()
+        .get("accept")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    let last_segment = req.uri().path().rsplit('/').next().unwrap_or("");
+    let looks_like_asset = last_segment.contains('.');
+    (req.method() == Method::GET || req.method() == Method::HEAD)
+        && accepts_html
+        && (config.spa_assets || !looks_like_asset)
+}
+
+//first of the configured index files present in dir
+async fn index_file(config: &Config, dir: &Path) -> Option<PathBuf> {
+    for name in config.index_files.iter() {
+        let candidate = dir.join(name);
+        if let Ok(metadata) = fs::metadata(&candidate).await {
+            let allowed = paths::check(&config.root, config.symlinks, &candidate).await;
+            if metadata.is_file() && allowed.is_ok() && !config.hides_real(&candidate).await {
+                return Some(candidate);
+            }
+        }
+    }
+    None
+}
+
+//the file that actually answers a request for some path
+struct Source {
+    path: PathBuf,
+    file: File,
+    metadata: Metadata,
+    //set when this is a precompressed sibling like app.wasm.br
+    encoding: Option<Encoding>,
+    //whether a different Accept-Encoding could have picked a different file
+    vary: bool,
+}
+
+async fn serve_file(req: &Request<Body>, config: &Config, path: &Path) -> Response<Body> {
+    let mut source = match File::open(path).await {
+        Ok(file) => match file.metadata().await {
+            Ok(metadata) if metadata.is_file() => Source {
+                path: path.to_path_buf(),
+                file,
+                metadata,
+                encoding: None,
+                vary: false,
+            },
+            Ok(_) => return not_found(),
+            Err(_) => return trouble(),
+        },
+        Err(_) => return not_found(),
+    };
+    if config.live_reload.is_some() {
+        match content_type(config, path, &mut source.file).await {
+            Ok(content_type) if content_type.starts_with("text/html") => {
+                return live_page(source.file, &content_type).await
+            }
+            Ok(_) => {}
+            Err(_) => return trouble(),
+        }
+    }
+    let source = if config.precompressed {
+        precompressed(req, config, source).await
     } else {
-        match File::open(path).await {
-            Ok(mut file) => {
-                let mut contents = vec![];
-                match file.read_to_end(&mut contents).await {
-                    Ok(_) => file_response(path, contents).await,
-                    Err(_) => trouble(),
+        source
+    };
+    file_response(req, config, path, source).await
+    //file goes out of scope and gets closed automagically
+}
+
+//swaps source for a sibling with a compressed copy if the client accepts its encoding
+async fn precompressed(req: &Request<Body>, config: &Config, source: Source) -> Source {
+    let mut available = vec![];
+    for encoding in encoding::ALL.iter() {
+        if let Some(sibling) = encoding.sibling(&source.path) {
+            //the deny list covers every file that can go out, app.js.gz included
+            let allowed = paths::check(&config.root, config.symlinks, &sibling)
+                .await
+                .is_ok()
+                && !config.hides_real(&sibling).await;
+            if allowed && fs::metadata(&sibling).await.is_ok_and(|m| m.is_file()) {
+                available.push(*encoding);
+            }
+        }
+    }
+    if available.is_empty() {
+        return source;
+    }
+    let accept = req
+        .headers()
+        .get("accept-encoding")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("");
+    let encoding = match encoding::negotiate(accept, &available) {
+        Some(encoding) => encoding,
+        None => {
+            return Source {
+                vary: true,
+                ..source
+            }
+        }
+    };
+    let sibling = encoding.sibling(&source.path).unwrap_or_default();
+    match File::open(&sibling).await {
+        Ok(file) => match file.metadata().await {
+            Ok(metadata) => Source {
+                path: sibling,
+                file,
+                metadata,
+                encoding: Some(encoding),
+                vary: true,
+            },
+            Err(_) => Source {
+                vary: true,
+                ..source
+            },
+        },
+        //gone since we looked, the original will do
+        Err(_) => Source {
+            vary: true,
+            ..source
+        },
+    }
+}
+
+//the body is streamed in chunks of this size rather than read into memory
+const CHUNK_SIZE: usize = 64 * 1024;
+
+type ByteStream = BoxStream<'static, io::Result<Bytes>>;
+
+async fn file_stream(mut file: File, range: Range<u64>) -> io::Result<ByteStream> {
+    file.seek(SeekFrom::Start(range.start)).await?;
+    let reader = file.take(range.end - range.start);
+    Ok(ReaderStream::with_capacity(reader, CHUNK_SIZE).boxed())
+}
+
+//path is what was asked for and decides the type, source is what gets sent
+async fn file_response(
+    req: &Request<Body>,
+    config: &Config,
+    path: &Path,
+    source: Source,
+) -> Response<Body> {
+    let Source {
+        path: source_path,
+        mut file,
+        metadata,
+        encoding: precoded,
+        vary,
+    } = source;
+    let mut validators = if config.etag_hash {
+        match Validators::from_contents(&source_path, &metadata).await {
+            Ok(validators) => validators,
+            Err(_) => return trouble(),
+        }
+    } else {
+        Validators::from_metadata(&metadata)
+    };
+    let content_type = match precoded {
+        //sniffing would only see compressed bytes
+        Some(_) => mime::with_charset(
+            mime::from_path(path, &config.mime_overrides).unwrap_or(mime::DEFAULT),
+        ),
+        None => match content_type(config, path, &mut file).await {
+            Ok(content_type) => content_type,
+            Err(_) => return trouble(),
+        },
+    };
+    let len = metadata.len();
+    let compressible = precoded.is_none()
+        && config.compress
+        && len >= config.compress_min_size
+        && len <= config.compress_max_size
+        && encoding::compressible(&content_type);
+    //range requests get the plain file so offsets keep meaning what the client expects
+    let coding = match req
+        .headers()
+        .get("accept-encoding")
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(accept) if compressible && !req.headers().contains_key("range") => {
+            encoding::negotiate(accept, &encoding::ALL)
+        }
+        _ => None,
+    };
+    if let Some(coding) = coding.or(precoded) {
+        validators.etag = coding.tag(&validators.etag);
+    }
+    let base = || {
+        let mut builder = with_validators(Response::builder(), &validators);
+        if compressible || vary {
+            builder = builder.header("Vary", "Accept-Encoding");
+        }
+        if let Some(coding) = precoded {
+            builder = builder.header("Content-Encoding", coding.name());
+        }
+        builder
+    };
+    match validators.evaluate(req) {
+        Outcome::Proceed => {}
+        Outcome::NotModified => return base().status(304).body(Body::empty()).unwrap(),
+        Outcome::PreconditionFailed => return precondition_failed(),
+    }
+    if let Some(coding) = coding {
+        let compressed = encoding::compress(file, coding, config.compress_level);
+        //the compressed length isn't known up front so this goes out chunked
+        return base()
+            .status(200)
+            .header("Content-type", &content_type)
+            .header("Content-Encoding", coding.name())
+            .body(Body::wrap_stream(ReaderStream::with_capacity(
+                compressed, CHUNK_SIZE,
+            )))
+            .unwrap();
+    }
+    let ranges = match req.headers().get("range").and_then(|v| v.to_str().ok()) {
+        Some(header) if validators.if_range(req) => range::parse(header, len),
+        _ => Ranges::Full,
+    };
+    let builder = base();
+    match ranges {
+        Ranges::Full => builder
+            .status(200)
+            .header("Content-type", &content_type)
+            .header("Accept-Ranges", "bytes")
+            .header("Content-Length", len)
+            .body(Body::wrap_stream(ReaderStream::with_capacity(
+                file, CHUNK_SIZE,
+            )))
+            .unwrap(),
+        Ranges::Unsatisfiable => builder
+            .status(416)
+            .header("Content-Range", format!("bytes */{}", len))
+            .body("range not satisfiable\r\n".into())
+            .unwrap(),
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0].clone();
+            let content_range = range::content_range(&range, len);
+            let part_len = range.end - range.start;
+            match file_stream(file, range).await {
+                Ok(stream) => builder
+                    .status(206)
+                    .header("Content-type", &content_type)
+                    .header("Accept-Ranges", "bytes")
+                    .header("Content-Range", content_range)
+                    .header("Content-Length", part_len)
+                    .body(Body::wrap_stream(stream))
+                    .unwrap(),
+                Err(_) => trouble(),
+            }
+        }
+        Ranges::Partial(ranges) => {
+            let boundary = range::boundary();
+            let mut parts: Vec<ByteStream> = vec![];
+            let mut body_len = 0;
+            //every part gets its own handle so each can seek independently
+            for range in ranges {
+                let header = range::part_header(&boundary, &content_type, &range, len);
+                body_len += header.len() as u64 + (range.end - range.start);
+                let part = match File::open(&source_path).await {
+                    Ok(file) => file_stream(file, range).await,
+                    Err(e) => Err(e),
+                };
+                match part {
+                    Ok(part) => {
+                        parts.push(stream::once(async { Ok(Bytes::from(header)) }).boxed());
+                        parts.push(part);
+                    }
+                    Err(_) => return trouble(),
                 }
             }
-            Err(_) => not_found(),
+            let closing = range::closing(&boundary);
+            body_len += closing.len() as u64;
+            parts.push(stream::once(async { Ok(Bytes::from(closing)) }).boxed());
+            builder
+                .status(206)
+                .header(
+                    "Content-type",
+                    format!("multipart/byteranges; boundary={}", boundary),
+                )
+                .header("Accept-Ranges", "bytes")
+                .header("Content-Length", body_len)
+                .body(Body::wrap_stream(stream::iter(parts).flatten()))
+                .unwrap()
         }
-        //file goes out of scope and gets closed automagically
     }
 }
 
-async fn file_response(path: &str, contents: Vec<u8>) -> Response<Body> {
+//html with the live reload script added, always fetched fresh so edits show up
+async fn live_page(mut file: File, content_type: &str) -> Response<Body> {
+    let mut html = vec![];
+    if file.read_to_end(&mut html).await.is_err() {
+        return trouble();
+    }
+    let page = livereload::inject(&html);
     Response::builder()
         .status(200)
-        .header("Content-type", mime_type(path))
-        .body(contents.into())
+        .header("Content-type", content_type)
+        .header("Cache-Control", "no-store")
+        .header("Content-Length", page.len())
+        .body(page.into())
         .unwrap()
 }
 
-fn mime_type(path: &str) -> &str {
-    let path = String::from(path).to_lowercase(); //we can shadow orig variable if we want to
-    if path.ends_with("html") {
-        "text/html"
-    } else if path.ends_with("htm") {
-        "text/html"
-    } else if path.ends_with("txt") {
-        "text/plain"
-    } else if path.ends_with("wasm") {
-        "application/wasm"
-    } else if path.ends_with("js") {
-        "text/javascript"
-    } else {
-        "application/octet-stream"
+fn with_validators(builder: response::Builder, validators: &Validators) -> response::Builder {
+    let builder = builder.header("ETag", &validators.etag);
+    match validators.last_modified_header() {
+        Some(date) => builder.header("Last-Modified", date),
+        None => builder,
     }
 }
 
-async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
-    let response = match req.uri().path() {
-        "/" => index_view(&req).await,
-        _ => file_view(&req).await,
+async fn content_type(config: &Config, path: &Path, file: &mut File) -> io::Result<String> {
+    let mime = match mime::from_path(path, &config.mime_overrides) {
+        Some(mime) => mime,
+        //files without an extension get a look at their first bytes
+        None if config.sniff && path.extension().is_none() => {
+            let mut prefix = vec![0; 512];
+            let n = file.read(&mut prefix).await?;
+            file.seek(SeekFrom::Start(0)).await?;
+            mime::sniff(&prefix[..n])
+        }
+        None => mime::DEFAULT,
     };
+    Ok(mime::with_charset(mime))
+}
+
+async fn handle(mut req: Request<Body>, config: Arc<Config>) -> Result<Response<Body>, Infallible> {
+    let response = route(&mut req, &config).await;
+    if config.quiet {
+        return Ok(response);
+    }
     //logging
     let now: DateTime<Utc> = Utc::now();
     let ua_agent = match req.headers().get("user-agent") {
@@ -147,28 +669,256 @@ async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
     Ok(response)
 }
 
+//writes only happen with --writable, everything else is read like a GET
+async fn route(req: &mut Request<Body>, config: &Config) -> Response<Body> {
+    match (req.method().as_str(), config.writable) {
+        ("PUT" | "POST" | "DELETE" | "MKCOL" | "MOVE", false) => method_not_allowed(config),
+        ("PUT", _) => write::put(req, config).await,
+        ("POST", _) => write::upload(req, config).await,
+        ("DELETE", _) => write::delete(req, config).await,
+        ("MKCOL", _) => write::mkcol(req, config).await,
+        ("MOVE", _) => write::move_to(req, config).await,
+        ("GET" | "HEAD", _) => file_view(req, config).await,
+        ("OPTIONS", _) => Response::builder()
+            .status(204)
+            .header("Allow", allowed(config))
+            .body(Body::empty())
+            .unwrap(),
+        _ => method_not_allowed(config),
+    }
+}
+
+fn serve_plain(
+    listener: TcpListener,
+    config: Arc<Config>,
+) -> Result<BoxFuture<'static, hyper::Result<()>>, String> {
+    let server = Server::from_tcp(listener).map_err(|e| e.to_string())?;
+    //without --h2c a client sending the HTTP/2 preface gets an HTTP/1 error
+    let server = http2(server.http1_only(!config.h2c), &config);
+    let make_svc = make_service_fn(move |_conn| {
+        let config = config.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, config.clone()))) }
+    });
+    Ok(server.serve(make_svc).boxed())
+}
+
+fn serve_tls(
+    listener: TcpListener,
+    shared: tls::Shared,
+    config: Arc<Config>,
+) -> Result<BoxFuture<'static, hyper::Result<()>>, String> {
+    let incoming = tls::incoming(listener, shared).map_err(|e| e.to_string())?;
+    let server = http2(Server::builder(accept::from_stream(incoming)), &config);
+    let make_svc = make_service_fn(move |_conn| {
+        let config = config.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, config.clone()))) }
+    });
+    Ok(server.serve(make_svc).boxed())
+}
+
+fn http2<I>(builder: server::Builder<I>, config: &Config) -> server::Builder<I> {
+    builder
+        .http2_max_concurrent_streams(config.h2_max_streams)
+        .http2_initial_stream_window_size(config.h2_stream_window)
+        .http2_initial_connection_window_size(config.h2_connection_window)
+}
+
+//plain HTTP listener whose only job is to send people to the HTTPS one
+fn serve_redirect(
+    listener: TcpListener,
+    https_port: u16,
+) -> Result<BoxFuture<'static, hyper::Result<()>>, String> {
+    let make_svc = make_service_fn(move |_conn| async move {
+        Ok::<_, Infallible>(service_fn(move |req| async move {
+            Ok::<_, Infallible>(to_https(&req, https_port))
+        }))
+    });
+    let server = Server::from_tcp(listener).map_err(|e| e.to_string())?;
+    Ok(server.serve(make_svc).boxed())
+}
+
+fn to_https(req: &Request<Body>, https_port: u16) -> Response<Body> {
+    let host = req
+        .headers()
+        .get("host")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok());
+    let host = match host {
+        Some(host) => host,
+        None => return bad_request(),
+    };
+    //the host header carries the http port, if any, so swap it for ours
+    let host = match (host.host(), https_port) {
+        (name, 443) => String::from(name),
+        (name, port) => format!("{}:{}", name, port),
+    };
+    let path = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    Response::builder()
+        .status(308)
+        .header("Location", format!("https://{}{}", host, path))
+        .body(Body::empty())
+        .unwrap()
+}
+
+//hands the url to whatever the desktop uses to open links
+fn open_browser(url: &str) {
+    let result = if cfg!(target_os = "macos") {
+        process::Command::new("open").arg(url).spawn()
+    } else if cfg!(windows) {
+        process::Command::new("cmd")
+            .args(["/C", "start", "", url])
+            .spawn()
+    } else {
+        process::Command::new("xdg-open").arg(url).spawn()
+    };
+    if let Err(e) = result {
+        eprintln!("couldn't open a browser: {}", e);
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    //port via PORT variable
-    let checked_port: Result<u16, std::num::ParseIntError> = match env::var("PORT") {
-        Ok(val) => val.parse(),
-        Err(_) => Ok(3000),
+    let mut config = match cli::parse(env::args().skip(1)) {
+        Ok(Action::Serve(config)) => *config,
+        Ok(Action::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Action::Version) => {
+            println!("mini-server {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(e) => {
+            eprintln!(
+                "error: {}\n\nRun with --help to see the available options.",
+                e
+            );
+            process::exit(2);
+        }
     };
-    let port = match checked_port {
-        Ok(n) => n,
-        _ => 3000,
+    //resolved once so the server doesn't care where it was started from
+    config.root = match std::fs::canonicalize(&config.root) {
+        Ok(root) if root.is_dir() => root,
+        Ok(root) => {
+            eprintln!("can't serve {}: not a directory", root.display());
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("can't serve {}: {}", config.root.display(), e);
+            process::exit(1);
+        }
     };
-    println!(
-        "starting server on 127.0.0.1:{}\nYou can use PORT environment variable to change this.",
-        port
-    );
-    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let listeners = match listen::bind_all(&config.bind, config.port, config.port_retries) {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            eprintln!("can't listen on {}", e);
+            process::exit(1);
+        }
+    };
+    if config.self_signed {
+        let dir = config
+            .self_signed_dir
+            .clone()
+            .unwrap_or_else(devcert::default_dir);
+        match devcert::ensure(&dir, &devcert::local_names()) {
+            Ok(generated) => {
+                println!("development CA {}", generated.ca.display());
+                println!("  SHA-256 {}", generated.fingerprint);
+                config.cert = Some(generated.cert);
+                config.key = Some(generated.key);
+            }
+            Err(e) => {
+                eprintln!("can't generate certificate: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    let tls = match (&config.cert, &config.key) {
+        (Some(cert), Some(key)) => match tls::load(cert, key) {
+            Ok(server_config) => {
+                let shared: tls::Shared = Arc::new(RwLock::new(Arc::new(server_config)));
+                tls::watch(shared.clone(), cert.clone(), key.clone());
+                Some(shared)
+            }
+            Err(e) => {
+                eprintln!("can't use certificate: {}", e);
+                process::exit(1);
+            }
+        },
+        _ => None,
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    //scripts can look for these lines (or the port file) to find the server
+    for listener in listeners.iter() {
+        println!("listening on {}", listener.addr);
+    }
+    let port = listeners[0].addr.port();
+    if let Some(path) = &config.port_file {
+        if let Err(e) = std::fs::write(path, format!("{}\n", port)) {
+            eprintln!("can't write port file {}: {}", path.display(), e);
+            process::exit(1);
+        }
+    }
+    let redirects = match config.redirect_http {
+        Some(redirect_port) => match listen::bind_all(&config.bind, redirect_port, 0) {
+            Ok(redirects) => redirects,
+            Err(e) => {
+                eprintln!("can't listen on {}", e);
+                process::exit(1);
+            }
+        },
+        None => vec![],
+    };
+    println!("serving {}", config.root.display());
+    println!("starting server on");
+    for listener in listeners.iter() {
+        for url in listen::urls(scheme, listener.addr, listener.dual_stack) {
+            println!("  {}", url);
+        }
+    }
+    for redirect in redirects.iter() {
+        println!("redirecting http://{}/ to https", redirect.addr);
+    }
+    println!("Run with --help to see the available options.");
+    if config.open {
+        let addr = listeners[0].addr;
+        let url = match addr.ip() {
+            ip if ip.is_unspecified() => format!("{}://localhost:{}/", scheme, port),
+            _ => listen::url(scheme, addr),
+        };
+        open_browser(&url);
+    }
 
-    let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+    let config = Arc::new(config);
+    livereload::watch(config.clone());
 
-    let server = Server::bind(&addr).serve(make_svc);
+    let mut servers: Vec<BoxFuture<'static, hyper::Result<()>>> = vec![];
+    for listener in listeners {
+        let server = match &tls {
+            Some(shared) => serve_tls(listener.listener, shared.clone(), config.clone()),
+            None => serve_plain(listener.listener, config.clone()),
+        };
+        match server {
+            Ok(server) => servers.push(server),
+            Err(e) => {
+                eprintln!("server error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+    for redirect in redirects {
+        match serve_redirect(redirect.listener, port) {
+            Ok(server) => servers.push(server),
+            Err(e) => {
+                eprintln!("server error: {}", e);
+                process::exit(1);
+            }
+        }
+    }
 
-    if let Err(e) = server.await {
-        eprintln!("server error: {}", e);
+    for result in future::join_all(servers).await {
+        if let Err(e) = result {
+            eprintln!("server error: {}", e);
+        }
     }
 }
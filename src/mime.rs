@@ -0,0 +1,310 @@
+//content types by file extension, with sniffing as a fallback for files without one
+use std::collections::HashMap;
+use std::path::Path;
+
+//sorted by extension so lookups can binary search
+const TYPES: &[(&str, &str)] = &[
+    ("7z", "application/x-7z-compressed"),
+    ("aac", "audio/aac"),
+    ("apng", "image/apng"),
+    ("atom", "application/atom+xml"),
+    ("avif", "image/avif"),
+    ("bin", "application/octet-stream"),
+    ("bmp", "image/bmp"),
+    ("bz2", "application/x-bzip2"),
+    ("cjs", "text/javascript"),
+    ("css", "text/css"),
+    ("csv", "text/csv"),
+    ("eot", "application/vnd.ms-fontobject"),
+    ("epub", "application/epub+zip"),
+    ("flac", "audio/flac"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html"),
+    ("html", "text/html"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("ics", "text/calendar"),
+    ("jar", "application/java-archive"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("jsonld", "application/ld+json"),
+    ("jxl", "image/jxl"),
+    ("m4a", "audio/mp4"),
+    ("m4v", "video/mp4"),
+    ("map", "application/json"),
+    ("md", "text/markdown"),
+    ("mid", "audio/midi"),
+    ("midi", "audio/midi"),
+    ("mjs", "text/javascript"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("mpeg", "video/mpeg"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("ogv", "video/ogg"),
+    ("opus", "audio/opus"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("rss", "application/rss+xml"),
+    ("rtf", "application/rtf"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("tgz", "application/gzip"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("toml", "application/toml"),
+    ("tsv", "text/tab-separated-values"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain"),
+    ("vtt", "text/vtt"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("weba", "audio/webm"),
+    ("webm", "video/webm"),
+    ("webmanifest", "application/manifest+json"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xhtml", "application/xhtml+xml"),
+    ("xml", "application/xml"),
+    ("xz", "application/x-xz"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("zip", "application/zip"),
+    ("zst", "application/zstd"),
+];
+
+pub const DEFAULT: &str = "application/octet-stream";
+
+//extension (lowercase, no dot) to type, these win over the built in table
+pub type Overrides = HashMap<String, String>;
+
+//the type for the extension after the last dot, if we know it
+pub fn from_path<'a>(path: &Path, overrides: &'a Overrides) -> Option<&'a str> {
+    let ext = path.extension()?.to_str()?.to_lowercase();
+    if let Some(mime) = overrides.get(&ext) {
+        return Some(mime);
+    }
+    TYPES
+        .binary_search_by(|(e, _)| (*e).cmp(ext.as_str()))
+        .ok()
+        .map(|i| TYPES[i].1)
+}
+
+//reads an Apache style mime.types file (type followed by extensions, # comments)
+//the nginx flavour wrapped in types { ... } with ; terminated lines works too
+pub fn parse_mime_types(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut entries = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let line = line.trim().trim_end_matches(';');
+        let line = line
+            .trim_start_matches("types")
+            .trim()
+            .trim_matches(['{', '}']);
+        let mut words = line.split_whitespace();
+        let mime = match words.next() {
+            Some(mime) => mime,
+            None => continue,
+        };
+        if !mime.contains('/') {
+            return Err(format!(
+                "line {}: {:?} is not a media type",
+                number + 1,
+                mime
+            ));
+        }
+        for ext in words {
+            entries.push((normalize_ext(ext), String::from(mime)));
+        }
+    }
+    Ok(entries)
+}
+
+//a single ext=type pair as given on the command line
+pub fn parse_override(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((ext, mime)) if !ext.trim().is_empty() && mime.contains('/') => {
+            Ok((normalize_ext(ext), String::from(mime.trim())))
+        }
+        _ => Err(format!("{:?} is not of the form ext=type", value)),
+    }
+}
+
+fn normalize_ext(ext: &str) -> String {
+    ext.trim().trim_start_matches('.').to_lowercase()
+}
+
+//textual types are always sent as utf-8
+pub fn with_charset(mime: &str) -> String {
+    let textual = mime.starts_with("text/")
+        || mime.ends_with("+xml")
+        || mime.ends_with("+json")
+        || mime == "application/json"
+        || mime == "application/xml";
+    if textual && !mime.contains("charset=") {
+        format!("{}; charset=utf-8", mime)
+    } else {
+        String::from(mime)
+    }
+}
+
+//guesses a type from the first few hundred bytes of a file
+pub fn sniff(prefix: &[u8]) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"\0asm", "application/wasm"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+        (b"wOFF", "font/woff"),
+        (b"wOF2", "font/woff2"),
+        (b"\x1aE\xdf\xa3", "video/webm"),
+    ];
+    if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| prefix.starts_with(magic)) {
+        return mime;
+    }
+    if prefix.len() >= 12 && &prefix[..4] == b"RIFF" && &prefix[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    if prefix.len() >= 12 && &prefix[4..8] == b"ftyp" {
+        return match &prefix[8..12] {
+            b"avif" | b"avis" => "image/avif",
+            b"qt  " => "video/quicktime",
+            _ => "video/mp4",
+        };
+    }
+    let text = match std::str::from_utf8(prefix) {
+        Ok(text) => text,
+        //the prefix may cut a multi-byte character in half
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&prefix[..e.valid_up_to()]).unwrap_or_default()
+        }
+        Err(_) => return DEFAULT,
+    };
+    let binary = text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b'));
+    if binary {
+        return DEFAULT;
+    }
+    let start = text.trim_start().to_lowercase();
+    if start.starts_with("<!doctype html") || start.starts_with("<html") {
+        "text/html"
+    } else if start.starts_with("<?xml") {
+        if start.contains("<svg") {
+            "image/svg+xml"
+        } else {
+            "application/xml"
+        }
+    } else if start.starts_with("<svg") {
+        "image/svg+xml"
+    } else {
+        "text/plain"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted() {
+        assert!(TYPES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn matches_whole_extensions() {
+        let none = Overrides::new();
+        assert_eq!(
+            from_path(Path::new("a/foo.json"), &none),
+            Some("application/json")
+        );
+        assert_eq!(
+            from_path(Path::new("APP.WASM"), &none),
+            Some("application/wasm")
+        );
+        assert_eq!(
+            from_path(Path::new("app.js.map"), &none),
+            Some("application/json")
+        );
+        assert_eq!(from_path(Path::new("x.notjs"), &none), None);
+        assert_eq!(from_path(Path::new("html"), &none), None);
+        assert_eq!(from_path(Path::new("Makefile"), &none), None);
+    }
+
+    #[test]
+    fn overrides_win() {
+        let mut overrides = Overrides::new();
+        let (ext, mime) = parse_override(".GLB=model/gltf-binary").unwrap();
+        overrides.insert(ext, mime);
+        overrides.insert(String::from("js"), String::from("application/javascript"));
+        assert_eq!(
+            from_path(Path::new("m.glb"), &overrides),
+            Some("model/gltf-binary")
+        );
+        assert_eq!(
+            from_path(Path::new("a.js"), &overrides),
+            Some("application/javascript")
+        );
+        assert_eq!(from_path(Path::new("a.css"), &overrides), Some("text/css"));
+        assert!(parse_override("glb").is_err());
+        assert!(parse_override("glb=binary").is_err());
+    }
+
+    #[test]
+    fn parses_mime_types_files() {
+        let apache = "# comment\nmodel/gltf-binary\tglb\nimage/ktx2 ktx2 # trailing\n\nmodel/vnd.usdz+zip usdz\n";
+        let nginx = "types {\n    application/x-pak  pak;\n    image/ktx2 ktx2 KTX;\n}\n";
+        assert_eq!(
+            parse_mime_types(apache).unwrap(),
+            vec![
+                (String::from("glb"), String::from("model/gltf-binary")),
+                (String::from("ktx2"), String::from("image/ktx2")),
+                (String::from("usdz"), String::from("model/vnd.usdz+zip")),
+            ]
+        );
+        assert_eq!(
+            parse_mime_types(nginx).unwrap(),
+            vec![
+                (String::from("pak"), String::from("application/x-pak")),
+                (String::from("ktx2"), String::from("image/ktx2")),
+                (String::from("ktx"), String::from("image/ktx2")),
+            ]
+        );
+        assert!(parse_mime_types("glb model").is_err());
+    }
+
+    #[test]
+    fn adds_charset_to_text() {
+        assert_eq!(with_charset("text/css"), "text/css; charset=utf-8");
+        assert_eq!(
+            with_charset("image/svg+xml"),
+            "image/svg+xml; charset=utf-8"
+        );
+        assert_eq!(with_charset("image/png"), "image/png");
+    }
+
+    #[test]
+    fn sniffs_common_formats() {
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n...."), "image/png");
+        assert_eq!(sniff(b"\0asm\x01\0\0\0"), "application/wasm");
+        assert_eq!(sniff(b"\0\0\0\x20ftypisom"), "video/mp4");
+        assert_eq!(sniff(b"  <!DOCTYPE html><html>"), "text/html");
+        assert_eq!(sniff(b"#!/bin/sh\necho hi\n"), "text/plain");
+        assert_eq!(sniff("caf\u{e9}".as_bytes()), "text/plain");
+        assert_eq!(sniff(b"caf\xc3"), "text/plain");
+        assert_eq!(sniff(b"\x00\x01\x02\x03"), DEFAULT);
+    }
+}
@@ -0,0 +1,413 @@
+//mapping request paths onto the filesystem without leaving the document root
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use tokio::fs;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SymlinkPolicy {
+    //links are treated as if they weren't there
+    Never,
+    //links are followed wherever they point
+    Always,
+    //links are followed as long as the target is inside the root
+    WithinRoot,
+}
+
+impl FromStr for SymlinkPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<SymlinkPolicy, String> {
+        match value.trim().to_lowercase().as_str() {
+            "never" => Ok(SymlinkPolicy::Never),
+            "always" => Ok(SymlinkPolicy::Always),
+            "within-root" => Ok(SymlinkPolicy::WithinRoot),
+            _ => Err(format!(
+                "{:?} is not one of never, always, within-root",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PathError {
+    //would end up outside the document root
+    Escapes,
+    //not valid UTF-8 once decoded, or contains a NUL byte
+    Invalid,
+    //goes through a symlink the policy doesn't allow following
+    Symlink,
+}
+
+//turns a url path into a path below root that the symlink policy allows
+pub async fn resolve(
+    root: &Path,
+    policy: SymlinkPolicy,
+    url_path: &str,
+) -> Result<PathBuf, PathError> {
+    let path = root.join(normalize(url_path)?);
+    check(root, policy, &path).await?;
+    Ok(path)
+}
+
+//path has to be below root already, this looks at the symlinks along the way
+pub async fn check(root: &Path, policy: SymlinkPolicy, path: &Path) -> Result<(), PathError> {
+    match policy {
+        SymlinkPolicy::Always => Ok(()),
+        SymlinkPolicy::WithinRoot => jail(root, path).await,
+        SymlinkPolicy::Never => no_symlinks(root, path).await,
+    }
+}
+
+//decodes the url path and folds . and .. segments lexically, the result is always relative
+pub fn normalize(url_path: &str) -> Result<PathBuf, PathError> {
+    let decoded = percent_decode_str(url_path)
+        .decode_utf8()
+        .map_err(|_| PathError::Invalid)?;
+    if decoded.contains('\0') {
+        return Err(PathError::Invalid);
+    }
+    let mut segments: Vec<&str> = vec![];
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                if segments.pop().is_none() {
+                    return Err(PathError::Escapes);
+                }
+            }
+            //anything the platform reads as more than a plain file name
+            //(a drive prefix, a separator like \ on windows) is refused
+            _ => match Path::new(segment)
+                .components()
+                .collect::<Vec<_>>()
+                .as_slice()
+            {
+                [Component::Normal(_)] => segments.push(segment),
+                _ => return Err(PathError::Escapes),
+            },
+        }
+    }
+    Ok(segments.iter().collect())
+}
+
+//the last segment of a name a client supplied, e.g. an upload's file name, or None
+//if nothing usable is left
+pub fn file_name(name: &str) -> Option<&str> {
+    //some browsers send the whole path the file was picked from
+    let name = name.rsplit(['/', '\\']).next()?;
+    if name.contains('\0') {
+        return None;
+    }
+    match Path::new(name).components().collect::<Vec<_>>().as_slice() {
+        [Component::Normal(_)] => Some(name),
+        _ => None,
+    }
+}
+
+//where path really points, for paths that don't exist yet the nearest existing
+//ancestor is resolved and the rest added back on
+pub async fn real(path: &Path) -> Option<PathBuf> {
+    for ancestor in path.ancestors() {
+        if let Ok(real) = fs::canonicalize(ancestor).await {
+            let rest = path.strip_prefix(ancestor).ok()?;
+            return Some(if rest.as_os_str().is_empty() {
+                real
+            } else {
+                real.join(rest)
+            });
+        }
+    }
+    None
+}
+
+async fn jail(root: &Path, path: &Path) -> Result<(), PathError> {
+    match real(path).await {
+        Some(real) if real.starts_with(root) => Ok(()),
+        _ => Err(PathError::Escapes),
+    }
+}
+
+//refuses path if it or any directory between it and root is a symlink
+async fn no_symlinks(root: &Path, path: &Path) -> Result<(), PathError> {
+    let relative = path.strip_prefix(root).map_err(|_| PathError::Escapes)?;
+    let mut current = root.to_path_buf();
+    for component in relative.components() {
+        current.push(component);
+        match fs::symlink_metadata(&current).await {
+            Ok(metadata) if metadata.file_type().is_symlink() => return Err(PathError::Symlink),
+            Ok(_) => {}
+            //nothing further down exists, so there are no more links either
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+//matches a relative path against a glob where * and ? stay within a segment and
+//** spans any number of segments, patterns without a / match the name at any depth
+pub fn glob(pattern: &str, path: &Path) -> bool {
+    let segments: Vec<String> = path
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if pattern.contains('/') {
+        let pattern: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+        match_segments(&pattern, &segments)
+    } else {
+        segments
+            .last()
+            .is_some_and(|name| match_segment(pattern, name))
+    }
+}
+
+fn match_segments(pattern: &[&str], segments: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => segments.is_empty(),
+        Some((&"**", rest)) => (0..=segments.len()).any(|i| match_segments(rest, &segments[i..])),
+        Some((first, rest)) => match segments.split_first() {
+            Some((segment, remaining)) => {
+                match_segment(first, segment) && match_segments(rest, remaining)
+            }
+            None => false,
+        },
+    }
+}
+
+//macOS and windows file systems ignore case, /SERVER.KEY opens server.key there
+const IGNORE_CASE: bool = cfg!(any(target_os = "macos", windows));
+
+fn match_segment(pattern: &str, name: &str) -> bool {
+    let fold = |s: &str| -> Vec<char> {
+        if IGNORE_CASE {
+            s.to_lowercase().chars().collect()
+        } else {
+            s.chars().collect()
+        }
+    };
+    let pattern = fold(pattern);
+    let name = fold(name);
+    //classic backtracking wildcard match, remembering the last * seen
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+//characters that can't appear literally in a path segment of a relative link
+//: is included so a name like a:b isn't read as a scheme
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'/')
+    .add(b':')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+//a file name made safe for use as (part of) an href
+pub fn encode_segment(name: &str) -> String {
+    utf8_percent_encode(name, SEGMENT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_plain_paths() {
+        assert_eq!(normalize("/"), Ok(PathBuf::new()));
+        assert_eq!(normalize("/a/b.txt"), Ok(PathBuf::from("a/b.txt")));
+        assert_eq!(normalize("//a//./b/"), Ok(PathBuf::from("a/b")));
+        assert_eq!(normalize("/a/../b"), Ok(PathBuf::from("b")));
+        assert_eq!(normalize("/a..b/c"), Ok(PathBuf::from("a..b/c")));
+        assert_eq!(
+            normalize("/my%20file.txt"),
+            Ok(PathBuf::from("my file.txt"))
+        );
+    }
+
+    #[test]
+    fn rejects_traversal_payloads() {
+        let payloads = [
+            "/..",
+            "/../etc/passwd",
+            "/a/../../etc/passwd",
+            "/%2e%2e/etc/passwd",
+            "/%2E%2E/%2E%2E/etc/passwd",
+            "/.%2e/etc/passwd",
+            "/%2e./etc/passwd",
+            "/a/%2e%2e/%2e%2e/etc/passwd",
+            "/..%2fetc/passwd",
+            "/%2e%2e%2fetc%2fpasswd",
+            "/a%2f..%2f..%2fetc/passwd",
+        ];
+        for payload in payloads.iter() {
+            assert_eq!(normalize(payload), Err(PathError::Escapes), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_encodings() {
+        let payloads = ["/%c0%ae%c0%ae/etc/passwd", "/%ff", "/a.txt%00.html", "/%00"];
+        for payload in payloads.iter() {
+            assert_eq!(normalize(payload), Err(PathError::Invalid), "{}", payload);
+        }
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob("*.key", Path::new("server.key")));
+        assert!(glob("*.key", Path::new("a/b/server.key")));
+        assert!(!glob("*.key", Path::new("server.key.txt")));
+        assert!(glob(".git/**", Path::new(".git")));
+        assert!(glob(".git/**", Path::new(".git/objects/ab")));
+        assert!(!glob(".git/**", Path::new("sub/.git/config")));
+        assert!(glob("**/.git/**", Path::new("sub/.git/config")));
+        assert!(glob("*.sw?", Path::new(".main.rs.swp")));
+        assert!(glob("build/*.map", Path::new("build/app.js.map")));
+        assert!(!glob("build/*.map", Path::new("build/js/app.js.map")));
+        assert_eq!(glob("*.key", Path::new("SERVER.KEY")), IGNORE_CASE);
+        assert_eq!(glob(".git/**", Path::new(".Git/config")), IGNORE_CASE);
+    }
+
+    #[test]
+    fn decodes_utf8_names() {
+        assert_eq!(normalize("/caf%C3%A9.txt"), Ok(PathBuf::from("café.txt")));
+        assert_eq!(encode_segment("café.txt"), "caf%C3%A9.txt");
+        assert_eq!(encode_segment("my report#1?.pdf"), "my%20report%231%3F.pdf");
+        assert_eq!(encode_segment("a:b"), "a%3Ab");
+    }
+
+    #[test]
+    fn keeps_only_plain_file_names() {
+        assert_eq!(file_name("report.pdf"), Some("report.pdf"));
+        assert_eq!(file_name("C:\\Users\\me\\report.pdf"), Some("report.pdf"));
+        assert_eq!(file_name("../../etc/passwd"), Some("passwd"));
+        assert_eq!(file_name(".."), None);
+        assert_eq!(file_name("a/.."), None);
+        assert_eq!(file_name(""), None);
+        assert_eq!(file_name("a\0b"), None);
+    }
+
+    #[test]
+    fn absolute_paths_stay_relative() {
+        assert_eq!(normalize("//etc/passwd"), Ok(PathBuf::from("etc/passwd")));
+        assert_eq!(normalize("/%2fetc/passwd"), Ok(PathBuf::from("etc/passwd")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn rejects_windows_specials() {
+        assert_eq!(normalize("/..%5c..%5cwindows"), Err(PathError::Escapes));
+        assert_eq!(normalize("/C:/windows"), Err(PathError::Escapes));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn applies_symlink_policy() {
+        let base = std::env::temp_dir().join(format!("mini-server-paths-{}", std::process::id()));
+        let root = base.join("root");
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(root.join("dir")).unwrap();
+        std::fs::write(base.join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("dir/file.txt"), "file").unwrap();
+        std::os::unix::fs::symlink(base.join("secret.txt"), root.join("out")).unwrap();
+        std::os::unix::fs::symlink(root.join("dir"), root.join("in")).unwrap();
+        let root = std::fs::canonicalize(&root).unwrap();
+
+        let policy = SymlinkPolicy::WithinRoot;
+        assert!(resolve(&root, policy, "/dir/file.txt").await.is_ok());
+        assert!(resolve(&root, policy, "/in/file.txt").await.is_ok());
+        assert!(resolve(&root, policy, "/missing/file.txt").await.is_ok());
+        assert_eq!(
+            resolve(&root, policy, "/out").await,
+            Err(PathError::Escapes)
+        );
+        assert_eq!(
+            resolve(&root, policy, "/../secret.txt").await,
+            Err(PathError::Escapes)
+        );
+
+        let policy = SymlinkPolicy::Never;
+        assert!(resolve(&root, policy, "/dir/file.txt").await.is_ok());
+        assert!(resolve(&root, policy, "/missing/file.txt").await.is_ok());
+        assert_eq!(
+            resolve(&root, policy, "/in/file.txt").await,
+            Err(PathError::Symlink)
+        );
+        assert_eq!(
+            resolve(&root, policy, "/out").await,
+            Err(PathError::Symlink)
+        );
+
+        let policy = SymlinkPolicy::Always;
+        assert!(resolve(&root, policy, "/in/file.txt").await.is_ok());
+        assert!(resolve(&root, policy, "/out").await.is_ok());
+        assert_eq!(
+            resolve(&root, policy, "/../secret.txt").await,
+            Err(PathError::Escapes)
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn links_to_hidden_files_are_hidden() {
+        use crate::config::Config;
+        let root = std::env::temp_dir().join(format!("mini-server-hides-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        std::fs::create_dir_all(root.join("public")).unwrap();
+        std::fs::write(root.join(".git/config"), "config").unwrap();
+        std::fs::write(root.join("server.key"), "key").unwrap();
+        std::fs::write(root.join("public/index.html"), "page").unwrap();
+        std::os::unix::fs::symlink(root.join("server.key"), root.join("exposed.txt")).unwrap();
+        std::os::unix::fs::symlink(root.join(".git"), root.join("g")).unwrap();
+        std::os::unix::fs::symlink(root.join("public"), root.join("site")).unwrap();
+        let root = std::fs::canonicalize(&root).unwrap();
+        let config = Config {
+            root: root.clone(),
+            deny: vec![String::from("*.key")],
+            ..Config::default()
+        };
+
+        assert!(!config.hides(&root.join("exposed.txt")));
+        assert!(config.hides_real(&root.join("exposed.txt")).await);
+        assert!(config.hides_real(&root.join("g")).await);
+        assert!(config.hides_real(&root.join("g/config")).await);
+        //not there yet, as for writes
+        assert!(config.hides_real(&root.join("g/hooks/new")).await);
+        assert!(!config.hides_real(&root.join("site/index.html")).await);
+        assert!(!config.hides_real(&root.join("site/new.html")).await);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
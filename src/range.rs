@@ -0,0 +1,179 @@
+//byte range requests as described in RFC 7233
+use std::ops::Range;
+
+//more ranges than this in one header is not a real client, RFC 7233 6.1 suggests
+//ignoring such requests rather than doing the work
+const MAX_RANGES: usize = 32;
+
+#[derive(Debug, PartialEq)]
+pub enum Ranges {
+    //no (usable) Range header, send the whole thing
+    Full,
+    Partial(Vec<Range<u64>>),
+    Unsatisfiable,
+}
+
+//parses the value of a Range header for a resource that is len bytes long
+//syntactically invalid headers and units other than bytes are ignored as the RFC asks
+pub fn parse(header: &str, len: u64) -> Ranges {
+    let specs = match header.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return Ranges::Full,
+    };
+    let mut ranges = vec![];
+    let mut seen_any = false;
+    for (i, spec) in specs
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .enumerate()
+    {
+        if i == MAX_RANGES {
+            return Ranges::Full;
+        }
+        seen_any = true;
+        let (first, last) = match spec.split_once('-') {
+            Some(pair) => pair,
+            None => return Ranges::Full,
+        };
+        let (first, last) = (first.trim(), last.trim());
+        if first.is_empty() {
+            //suffix range: the last n bytes
+            let n: u64 = match last.parse() {
+                Ok(n) => n,
+                Err(_) => return Ranges::Full,
+            };
+            if n > 0 && len > 0 {
+                ranges.push(len.saturating_sub(n)..len);
+            }
+        } else {
+            let first: u64 = match first.parse() {
+                Ok(n) => n,
+                Err(_) => return Ranges::Full,
+            };
+            let last: u64 = if last.is_empty() {
+                u64::MAX
+            } else {
+                match last.parse() {
+                    Ok(n) => n,
+                    Err(_) => return Ranges::Full,
+                }
+            };
+            if last < first {
+                return Ranges::Full;
+            }
+            if first < len {
+                ranges.push(first..last.min(len - 1) + 1);
+            }
+        }
+    }
+    if !seen_any {
+        Ranges::Full
+    } else if ranges.is_empty() {
+        Ranges::Unsatisfiable
+    } else {
+        Ranges::Partial(coalesce(ranges))
+    }
+}
+
+//overlapping and adjacent ranges become one, so nobody gets the same bytes twice
+fn coalesce(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.sort_by_key(|range| range.start);
+    let mut merged: Vec<Range<u64>> = vec![];
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+pub fn content_range(range: &Range<u64>, len: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end - 1, len)
+}
+
+//separator for multipart/byteranges bodies, it only has to be absent from the parts
+pub fn boundary() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("mini-server-{:x}", nanos)
+}
+
+pub fn part_header(boundary: &str, content_type: &str, range: &Range<u64>, len: u64) -> String {
+    format!(
+        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+        boundary,
+        content_type,
+        content_range(range, len)
+    )
+}
+
+pub fn closing(boundary: &str) -> String {
+    format!("\r\n--{}--\r\n", boundary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one(range: Range<u64>) -> Ranges {
+        Ranges::Partial(vec![range])
+    }
+
+    #[test]
+    fn parses_single_ranges() {
+        assert_eq!(parse("bytes=0-9", 100), one(0..10));
+        assert_eq!(parse("bytes=90-", 100), one(90..100));
+        assert_eq!(parse("bytes=90-200", 100), one(90..100));
+        assert_eq!(parse("bytes=-10", 100), one(90..100));
+        assert_eq!(parse("bytes=-200", 100), one(0..100));
+        assert_eq!(parse("BYTES = 5-5", 100), one(5..6));
+    }
+
+    #[test]
+    fn ignores_invalid_headers() {
+        assert_eq!(parse("bytes=5-2", 100), Ranges::Full);
+        assert_eq!(parse("bytes=a-b", 100), Ranges::Full);
+        assert_eq!(parse("bytes=5", 100), Ranges::Full);
+        assert_eq!(parse("bytes=", 100), Ranges::Full);
+        assert_eq!(parse("items=0-5", 100), Ranges::Full);
+        assert_eq!(parse("0-5", 100), Ranges::Full);
+    }
+
+    #[test]
+    fn refuses_unsatisfiable_ranges() {
+        assert_eq!(parse("bytes=100-", 100), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 100), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=0-0", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-5", 0), Ranges::Unsatisfiable);
+        //one satisfiable range is enough
+        assert_eq!(parse("bytes=200-300,0-0", 100), one(0..1));
+    }
+
+    #[test]
+    fn merges_overlapping_ranges() {
+        assert_eq!(
+            parse("bytes=50-59,0-9,5-14,15-19", 100),
+            Ranges::Partial(vec![0..20, 50..60])
+        );
+        assert_eq!(parse("bytes=0-0,0-0,0-0", 100), one(0..1));
+        assert_eq!(parse("bytes=-10,80-", 100), one(80..100));
+    }
+
+    #[test]
+    fn caps_the_number_of_ranges() {
+        let spaced = (0..MAX_RANGES)
+            .map(|i| format!("{}-{}", i * 2, i * 2))
+            .collect::<Vec<_>>()
+            .join(",");
+        match parse(&format!("bytes={}", spaced), 1000) {
+            Ranges::Partial(ranges) => assert_eq!(ranges.len(), MAX_RANGES),
+            other => panic!("{:?}", other),
+        }
+        let many = vec!["0-0"; 1500].join(",");
+        assert_eq!(parse(&format!("bytes={}", many), 100), Ranges::Full);
+    }
+}
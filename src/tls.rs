@@ -0,0 +1,108 @@
+//HTTPS: certificate loading, hot reloading and the TLS accept loop
+use futures_util::stream::{self, Stream};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+//the config new connections are accepted with, swapped out when certificates change
+pub type Shared = Arc<RwLock<Arc<ServerConfig>>>;
+
+//how often the certificate files are checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+//clients that open a connection and never finish the handshake are dropped after this
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn server_config(cert_pem: &[u8], key_pem: &[u8]) -> Result<ServerConfig, String> {
+    let certs = CertificateDer::pem_slice_iter(cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("bad certificate: {}", e))?;
+    if certs.is_empty() {
+        return Err(String::from("no certificates found"));
+    }
+    let key = PrivateKeyDer::from_pem_slice(key_pem).map_err(|e| format!("bad key: {}", e))?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| e.to_string())?;
+    //hyper picks the protocol from the connection preface, ALPN just lets clients know
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+pub fn load(cert: &Path, key: &Path) -> Result<ServerConfig, String> {
+    let cert_pem = std::fs::read(cert).map_err(|e| format!("{}: {}", cert.display(), e))?;
+    let key_pem = std::fs::read(key).map_err(|e| format!("{}: {}", key.display(), e))?;
+    server_config(&cert_pem, &key_pem)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+//polls the certificate and key and swaps in a new config when either changes
+//a broken pair (say, halfway through a renewal) keeps the old one in place
+pub fn watch(shared: Shared, cert: PathBuf, key: PathBuf) {
+    tokio::spawn(async move {
+        let mut seen = (modified(&cert), modified(&key));
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        loop {
+            interval.tick().await;
+            let current = (modified(&cert), modified(&key));
+            if current == seen {
+                continue;
+            }
+            match load(&cert, &key) {
+                Ok(config) => {
+                    *shared.write().unwrap() = Arc::new(config);
+                    seen = current;
+                    println!("reloaded certificate {}", cert.display());
+                }
+                Err(e) => eprintln!("couldn't reload certificate: {}", e),
+            }
+        }
+    });
+}
+
+//accepts connections and runs the handshakes off to the side, so one slow client
+//doesn't hold up the others
+pub fn incoming(
+    listener: std::net::TcpListener,
+    shared: Shared,
+) -> io::Result<impl Stream<Item = io::Result<TlsStream<TcpStream>>>> {
+    let listener = TcpListener::from_std(listener)?;
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        loop {
+            let (tcp, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    //usually out of file descriptors, give it a moment
+                    eprintln!("accept error: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let acceptor = TlsAcceptor::from(shared.read().unwrap().clone());
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                //failed handshakes are mostly browsers rejecting the certificate
+                let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(tcp));
+                if let Ok(Ok(tls)) = handshake.await {
+                    let _ = tx.send(Ok(tls)).await;
+                }
+            });
+        }
+    });
+    Ok(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|conn| (conn, rx))
+    }))
+}
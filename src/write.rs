@@ -0,0 +1,712 @@
+//--writable: PUT, multipart uploads, DELETE, MKCOL and MOVE, all inside the root
+use crate::conditional::{Outcome, Validators};
+use crate::config::Config;
+use crate::paths;
+use crate::{
+    bad_request, conflict, forbidden, method_not_allowed, not_found, precondition_failed, rejected,
+    too_large, trouble,
+};
+use futures_util::stream::{Stream, StreamExt};
+use hyper::body::Bytes;
+use hyper::{Body, Request, Response, Uri};
+use multer::Multipart;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::{self, File};
+use tokio::io::AsyncWriteExt;
+
+//keeps temp file names apart when several uploads run at once
+static UPLOADS: AtomicU64 = AtomicU64::new(0);
+
+enum Failure {
+    //more than the limit was sent
+    TooLarge,
+    //the file is there and may not be replaced
+    Exists,
+    //the directory to write into doesn't exist
+    NoParent,
+    //the client went away or sent something malformed
+    Body,
+    Io,
+}
+
+fn failed(failure: Failure) -> Response<Body> {
+    match failure {
+        Failure::TooLarge => too_large(),
+        Failure::Exists | Failure::NoParent => conflict(),
+        Failure::Body => bad_request(),
+        Failure::Io => trouble(),
+    }
+}
+
+//maps a url path the same way file_view does, hidden paths can't be written either
+async fn target(config: &Config, url_path: &str) -> Result<PathBuf, Response<Body>> {
+    let path = paths::resolve(&config.root, config.symlinks, url_path)
+        .await
+        .map_err(rejected)?;
+    if config.hides_real(&path).await {
+        return Err(not_found());
+    }
+    Ok(path)
+}
+
+//PUT /path: the body becomes the file at path, replacing it only with --overwrite
+pub async fn put(req: &mut Request<Body>, config: &Config) -> Response<Body> {
+    if !same_origin(req) {
+        return forbidden();
+    }
+    let path = match target(config, req.uri().path()).await {
+        Ok(path) => path,
+        Err(response) => return response,
+    };
+    //directories aren't something a body can become
+    if req.uri().path().ends_with('/') || path == config.root {
+        return conflict();
+    }
+    let existing = fs::metadata(&path).await.ok();
+    if existing.as_ref().is_some_and(|m| m.is_dir()) {
+        return conflict();
+    }
+    //If-None-Match: * asks for the file only if it doesn't exist yet
+    let create_only = req
+        .headers()
+        .get("if-none-match")
+        .is_some_and(|v| v.as_bytes() == b"*");
+    //If-Match and If-Unmodified-Since keep a stale client from replacing newer contents,
+    //If-None-Match: * from replacing anything
+    match &existing {
+        Some(metadata) => {
+            let validators = if config.etag_hash {
+                match Validators::from_contents(&path, metadata).await {
+                    Ok(validators) => validators,
+                    Err(_) => return trouble(),
+                }
+            } else {
+                Validators::from_metadata(metadata)
+            };
+            if let Outcome::PreconditionFailed = validators.evaluate(req) {
+                return precondition_failed();
+            }
+            if !config.overwrite {
+                return conflict();
+            }
+        }
+        //there is nothing for a tag to match
+        None if req.headers().contains_key("if-match") => return precondition_failed(),
+        None => {}
+    }
+    if declared_length(req).is_some_and(|len| len > config.max_upload) {
+        return too_large();
+    }
+    let body = std::mem::take(req.body_mut());
+    let replace = config.overwrite && !create_only;
+    match store(body, &path, config.max_upload, replace).await {
+        Ok(_) if existing.is_some() => Response::builder().status(204).body(Body::empty()).unwrap(),
+        Ok(_) => Response::builder()
+            .status(201)
+            .header("Location", req.uri().path())
+            .body("created\r\n".into())
+            .unwrap(),
+        Err(Failure::Exists) if create_only => precondition_failed(),
+        Err(failure) => failed(failure),
+    }
+}
+
+//POST to a directory with multipart/form-data, as sent by the listing's upload form
+pub async fn upload(req: &mut Request<Body>, config: &Config) -> Response<Body> {
+    if !same_origin(req) {
+        return forbidden();
+    }
+    let dir = match target(config, req.uri().path()).await {
+        Ok(path) => path,
+        Err(response) => return response,
+    };
+    match fs::metadata(&dir).await {
+        Ok(metadata) if metadata.is_dir() => {}
+        Ok(_) => return method_not_allowed(config),
+        Err(_) => return not_found(),
+    }
+    let boundary = match req
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| multer::parse_boundary(v).ok())
+    {
+        Some(boundary) => boundary,
+        None => return bad_request(),
+    };
+    if declared_length(req).is_some_and(|len| len > config.max_upload) {
+        return too_large();
+    }
+    let mut multipart = Multipart::new(std::mem::take(req.body_mut()), boundary);
+    //the limit covers all the files together
+    let mut remaining = config.max_upload;
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(_) => return bad_request(),
+        };
+        //other form fields, and file inputs left empty
+        let name = match field.file_name().and_then(paths::file_name) {
+            Some(name) => PathBuf::from(name),
+            None => continue,
+        };
+        let path = dir.join(name);
+        if config.hides_real(&path).await {
+            return not_found();
+        }
+        if let Err(e) = paths::check(&config.root, config.symlinks, &path).await {
+            return rejected(e);
+        }
+        if fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+            return conflict();
+        }
+        match store(field, &path, remaining, config.overwrite).await {
+            Ok(written) => remaining -= written,
+            Err(failure) => return failed(failure),
+        }
+    }
+    //back to the listing, so a refresh doesn't send everything again
+    Response::builder()
+        .status(303)
+        .header("Location", req.uri().to_string())
+        .body(Body::empty())
+        .unwrap()
+}
+
+//DELETE /path: removes a file, or a directory if it's empty
+pub async fn delete(req: &mut Request<Body>, config: &Config) -> Response<Body> {
+    if !same_origin(req) {
+        return forbidden();
+    }
+    let path = match target(config, req.uri().path()).await {
+        Ok(path) => path,
+        Err(response) => return response,
+    };
+    if path == config.root {
+        return forbidden();
+    }
+    //a link is removed itself, never what it points to
+    let removed = match fs::symlink_metadata(&path).await {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir(&path).await,
+        Ok(_) => fs::remove_file(&path).await,
+        Err(_) => return not_found(),
+    };
+    match removed {
+        Ok(()) => Response::builder().status(204).body(Body::empty()).unwrap(),
+        Err(e) => io_failed(e),
+    }
+}
+
+//MKCOL /path: creates one directory, its parent has to exist already
+pub async fn mkcol(req: &mut Request<Body>, config: &Config) -> Response<Body> {
+    if !same_origin(req) {
+        return forbidden();
+    }
+    let path = match target(config, req.uri().path()).await {
+        Ok(path) => path,
+        Err(response) => return response,
+    };
+    //a body would describe what to create, which we don't support
+    if declared_length(req).is_some_and(|len| len > 0) {
+        return Response::builder()
+            .status(415)
+            .body("unsupported media type\r\n".into())
+            .unwrap();
+    }
+    match fs::create_dir(&path).await {
+        Ok(()) => Response::builder()
+            .status(201)
+            .header("Location", with_slash(req.uri().path()))
+            .body("created\r\n".into())
+            .unwrap(),
+        //something is already there
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => method_not_allowed(config),
+        Err(e) => io_failed(e),
+    }
+}
+
+//MOVE /path with a Destination header: renames within the root, replacing an
+//existing destination only with --overwrite and without Overwrite: F
+pub async fn move_to(req: &mut Request<Body>, config: &Config) -> Response<Body> {
+    if !same_origin(req) {
+        return forbidden();
+    }
+    let destination = match req
+        .headers()
+        .get("destination")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<Uri>().ok())
+    {
+        Some(destination) => destination,
+        None => return bad_request(),
+    };
+    //other servers aren't ours to write to
+    if let Some(authority) = destination.authority() {
+        if request_host(req).is_none_or(|host| !authority.as_str().eq_ignore_ascii_case(host)) {
+            return forbidden();
+        }
+    }
+    let from = match target(config, req.uri().path()).await {
+        Ok(path) => path,
+        Err(response) => return response,
+    };
+    let to = match target(config, destination.path()).await {
+        Ok(path) => path,
+        Err(response) => return response,
+    };
+    if from == config.root {
+        return forbidden();
+    }
+    //the root can't be replaced and a directory can't go inside itself
+    if to == config.root || to.starts_with(&from) {
+        return conflict();
+    }
+    let source = match fs::symlink_metadata(&from).await {
+        Ok(metadata) => metadata,
+        Err(_) => return not_found(),
+    };
+    let existing = fs::symlink_metadata(&to).await.ok();
+    let overwrite_header = req
+        .headers()
+        .get("overwrite")
+        .is_none_or(|v| !v.as_bytes().eq_ignore_ascii_case(b"f"));
+    let replace = config.overwrite && overwrite_header;
+    match existing {
+        Some(_) if !overwrite_header => return precondition_failed(),
+        Some(_) if !replace => return conflict(),
+        _ => {}
+    }
+    let moved = if replace || source.is_dir() {
+        //directories can't be linked, existing was checked just above
+        fs::rename(&from, &to).await
+    } else {
+        //same as uploads, a file that appeared meanwhile isn't replaced
+        move_new(&from, &to).await
+    };
+    match moved {
+        Ok(()) if existing.is_some() => {
+            Response::builder().status(204).body(Body::empty()).unwrap()
+        }
+        Ok(()) => Response::builder()
+            .status(201)
+            .header("Location", destination.path())
+            .body("created\r\n".into())
+            .unwrap(),
+        Err(e) => io_failed(e),
+    }
+}
+
+fn io_failed(e: io::Error) -> Response<Body> {
+    match e.kind() {
+        //a missing parent, a non empty directory, a file where a directory should be
+        io::ErrorKind::NotFound
+        | io::ErrorKind::AlreadyExists
+        | io::ErrorKind::DirectoryNotEmpty
+        | io::ErrorKind::IsADirectory
+        | io::ErrorKind::NotADirectory => conflict(),
+        io::ErrorKind::PermissionDenied => forbidden(),
+        _ => trouble(),
+    }
+}
+
+fn with_slash(path: &str) -> String {
+    if path.ends_with('/') {
+        String::from(path)
+    } else {
+        format!("{}/", path)
+    }
+}
+
+//writes body next to path under a hidden name and moves it into place once complete,
+//so nobody ever reads half a file
+async fn store<S, E>(body: S, path: &Path, limit: u64, replace: bool) -> Result<u64, Failure>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    let name = path.file_name().ok_or(Failure::NoParent)?;
+    let temp = path.with_file_name(format!(
+        ".{}.upload-{}-{}",
+        name.to_string_lossy(),
+        std::process::id(),
+        UPLOADS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let mut result = write_temp(body, &temp, limit).await;
+    if result.is_ok() {
+        if let Err(failure) = place(&temp, path, replace).await {
+            result = Err(failure);
+        }
+    }
+    if result.is_err() {
+        let _ = fs::remove_file(&temp).await;
+    }
+    result
+}
+
+async fn write_temp<S, E>(mut body: S, temp: &Path, limit: u64) -> Result<u64, Failure>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    let mut file = match File::create(temp).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(Failure::NoParent),
+        Err(_) => return Err(Failure::Io),
+    };
+    let mut written: u64 = 0;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|_| Failure::Body)?;
+        written += chunk.len() as u64;
+        if written > limit {
+            return Err(Failure::TooLarge);
+        }
+        file.write_all(&chunk).await.map_err(|_| Failure::Io)?;
+    }
+    file.sync_all().await.map_err(|_| Failure::Io)?;
+    Ok(written)
+}
+
+async fn place(temp: &Path, path: &Path, replace: bool) -> Result<(), Failure> {
+    if replace {
+        return fs::rename(temp, path).await.map_err(|_| Failure::Io);
+    }
+    match move_new(temp, path).await {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(Failure::Exists),
+        Err(_) => Err(Failure::Io),
+    }
+}
+
+//a rename replaces whatever is there, a hard link refuses to, even if the file
+//showed up after we last looked
+async fn move_new(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to).await {
+        Ok(()) => {
+            let _ = fs::remove_file(from).await;
+            Ok(())
+        }
+        //filesystems without links (FAT, some network mounts) get the name claimed
+        //exclusively first, the rename then only replaces our own empty file
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::Unsupported | io::ErrorKind::PermissionDenied
+            ) =>
+        {
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(to)
+                .await?;
+            fs::rename(from, to).await
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn declared_length(req: &Request<Body>) -> Option<u64> {
+    req.headers()
+        .get("content-length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+}
+
+//browsers send Origin with form posts, writes from pages on other sites are refused
+//so they can't upload through a visitor's browser
+fn same_origin(req: &Request<Body>) -> bool {
+    let origin = match req.headers().get("origin") {
+        Some(origin) => origin.to_str().ok(),
+        None => return true,
+    };
+    match (origin.and_then(|o| o.split_once("://")), request_host(req)) {
+        (Some((_, authority)), Some(host)) => authority.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+//HTTP/2 carries the host in the uri instead of a header
+fn request_host(req: &Request<Body>) -> Option<&str> {
+    req.uri()
+        .authority()
+        .map(|a| a.as_str())
+        .or_else(|| req.headers().get("host").and_then(|v| v.to_str().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conditional::http_date;
+
+    //a writable config serving a fresh, empty directory
+    fn fixture(name: &str) -> Config {
+        let root =
+            std::env::temp_dir().join(format!("mini-server-write-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        Config {
+            root: std::fs::canonicalize(&root).unwrap(),
+            writable: true,
+            ..Config::default()
+        }
+    }
+
+    fn request(method: &str, uri: &str, headers: &[(&str, &str)], body: &str) -> Request<Body> {
+        let mut builder = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("host", "localhost");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn put_file(config: &Config, uri: &str, headers: &[(&str, &str)], body: &str) -> u16 {
+        put(&mut request("PUT", uri, headers, body), config)
+            .await
+            .status()
+            .as_u16()
+    }
+
+    fn form(file_name: &str, contents: &str) -> Request<Body> {
+        let body = format!(
+            "--X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+             Content-Type: text/plain\r\n\r\n{}\r\n--X--\r\n",
+            file_name, contents
+        );
+        request(
+            "POST",
+            "/",
+            &[("content-type", "multipart/form-data; boundary=X")],
+            &body,
+        )
+    }
+
+    fn read(config: &Config, name: &str) -> String {
+        std::fs::read_to_string(config.root.join(name)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn put_only_overwrites_when_allowed() {
+        let mut config = fixture("overwrite");
+        assert_eq!(put_file(&config, "/a.txt", &[], "one").await, 201);
+        assert_eq!(put_file(&config, "/a.txt", &[], "two").await, 409);
+        assert_eq!(read(&config, "a.txt"), "one");
+        //directories aren't something a body can become
+        assert_eq!(put_file(&config, "/dir/", &[], "x").await, 409);
+        assert_eq!(put_file(&config, "/missing/a.txt", &[], "x").await, 409);
+
+        config.overwrite = true;
+        assert_eq!(put_file(&config, "/a.txt", &[], "two").await, 204);
+        assert_eq!(read(&config, "a.txt"), "two");
+        let create_only = [("if-none-match", "*")];
+        assert_eq!(
+            put_file(&config, "/a.txt", &create_only, "three").await,
+            412
+        );
+        assert_eq!(put_file(&config, "/b.txt", &create_only, "new").await, 201);
+        assert_eq!(read(&config, "a.txt"), "two");
+        std::fs::remove_dir_all(&config.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn put_checks_preconditions() {
+        let mut config = fixture("preconditions");
+        config.overwrite = true;
+        assert_eq!(put_file(&config, "/a.txt", &[], "one").await, 201);
+        let metadata = std::fs::metadata(config.root.join("a.txt")).unwrap();
+        let validators = Validators::from_metadata(&metadata);
+        let modified = http_date(validators.last_modified.unwrap());
+
+        let stale = [("if-match", "\"stale\"")];
+        assert_eq!(put_file(&config, "/a.txt", &stale, "lost").await, 412);
+        let old = [("if-unmodified-since", "Thu, 01 Jan 1970 00:00:00 GMT")];
+        assert_eq!(put_file(&config, "/a.txt", &old, "lost").await, 412);
+        assert_eq!(read(&config, "a.txt"), "one");
+        //no file, nothing to match
+        assert_eq!(put_file(&config, "/new.txt", &stale, "lost").await, 412);
+
+        let current = [("if-unmodified-since", modified.as_str())];
+        assert_eq!(put_file(&config, "/a.txt", &current, "two").await, 204);
+        let metadata = std::fs::metadata(config.root.join("a.txt")).unwrap();
+        let etag = Validators::from_metadata(&metadata).etag;
+        let matching = [("if-match", etag.as_str())];
+        assert_eq!(put_file(&config, "/a.txt", &matching, "three").await, 204);
+        assert_eq!(read(&config, "a.txt"), "three");
+        std::fs::remove_dir_all(&config.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn writes_stay_under_the_upload_limit() {
+        let mut config = fixture("limit");
+        config.max_upload = 4;
+        //whether or not the length is declared up front
+        assert_eq!(put_file(&config, "/a.txt", &[], "too long").await, 413);
+        let declared = [("content-length", "8")];
+        assert_eq!(
+            put_file(&config, "/a.txt", &declared, "too long").await,
+            413
+        );
+        assert_eq!(put_file(&config, "/a.txt", &[], "fits").await, 201);
+        let response = upload(&mut form("b.txt", "too long"), &config).await;
+        assert_eq!(response.status(), 413);
+        //nothing half written is left behind
+        let names: Vec<_> = std::fs::read_dir(&config.root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["a.txt"]);
+        std::fs::remove_dir_all(&config.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_writes_from_other_sites() {
+        let config = fixture("origin");
+        let other = [("origin", "https://evil.example")];
+        assert_eq!(put_file(&config, "/a.txt", &other, "x").await, 403);
+        let mut req = form("a.txt", "x");
+        req.headers_mut()
+            .insert("origin", "https://evil.example".parse().unwrap());
+        assert_eq!(upload(&mut req, &config).await.status(), 403);
+        assert!(!config.root.join("a.txt").exists());
+
+        let same = [("origin", "http://localhost")];
+        assert_eq!(put_file(&config, "/a.txt", &same, "x").await, 201);
+        std::fs::remove_dir_all(&config.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn hidden_names_cant_be_written() {
+        let mut config = fixture("hidden");
+        config.deny = vec![String::from("*.key")];
+        assert_eq!(put_file(&config, "/.env", &[], "x").await, 404);
+        assert_eq!(put_file(&config, "/server.key", &[], "x").await, 404);
+        assert_eq!(put_file(&config, "/.git/config", &[], "x").await, 404);
+        for name in [".htaccess", "server.key"] {
+            assert_eq!(upload(&mut form(name, "x"), &config).await.status(), 404);
+        }
+        assert_eq!(std::fs::read_dir(&config.root).unwrap().count(), 0);
+        std::fs::remove_dir_all(&config.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn uploads_keep_only_the_file_name() {
+        let config = fixture("upload");
+        for (sent, stored) in [
+            ("../../evil.txt", "evil.txt"),
+            ("C:\\Users\\me\\report.txt", "report.txt"),
+            ("plain.txt", "plain.txt"),
+        ] {
+            let response = upload(&mut form(sent, sent), &config).await;
+            assert_eq!(response.status(), 303, "{}", sent);
+            assert_eq!(read(&config, stored), sent);
+        }
+        //a name with nothing usable left is skipped
+        assert_eq!(upload(&mut form("..", "x"), &config).await.status(), 303);
+        assert_eq!(std::fs::read_dir(&config.root).unwrap().count(), 3);
+        //and an existing file isn't replaced without --overwrite
+        assert_eq!(
+            upload(&mut form("plain.txt", "x"), &config).await.status(),
+            409
+        );
+        assert_eq!(read(&config, "plain.txt"), "plain.txt");
+        std::fs::remove_dir_all(&config.root).unwrap();
+    }
+
+    async fn send(config: &Config, method: &str, uri: &str, headers: &[(&str, &str)]) -> u16 {
+        let mut req = request(method, uri, headers, "");
+        let response = match method {
+            "DELETE" => delete(&mut req, config).await,
+            "MKCOL" => mkcol(&mut req, config).await,
+            _ => move_to(&mut req, config).await,
+        };
+        response.status().as_u16()
+    }
+
+    #[tokio::test]
+    async fn deletes_within_the_root() {
+        let mut config = fixture("delete");
+        config.deny = vec![String::from("*.key")];
+        let root = config.root.clone();
+        std::fs::create_dir_all(root.join("full/inner")).unwrap();
+        std::fs::create_dir(root.join("empty")).unwrap();
+        for name in ["a.txt", ".env", "server.key"] {
+            std::fs::write(root.join(name), "x").unwrap();
+        }
+        assert_eq!(send(&config, "DELETE", "/", &[]).await, 403);
+        assert_eq!(send(&config, "DELETE", "/../a.txt", &[]).await, 403);
+        assert_eq!(send(&config, "DELETE", "/full", &[]).await, 409);
+        assert_eq!(send(&config, "DELETE", "/.env", &[]).await, 404);
+        assert_eq!(send(&config, "DELETE", "/server.key", &[]).await, 404);
+        assert_eq!(send(&config, "DELETE", "/missing", &[]).await, 404);
+        assert!(root.join("full/inner").exists());
+        assert!(root.join(".env").exists() && root.join("server.key").exists());
+
+        assert_eq!(send(&config, "DELETE", "/empty", &[]).await, 204);
+        assert_eq!(send(&config, "DELETE", "/a.txt", &[]).await, 204);
+        assert!(!root.join("empty").exists() && !root.join("a.txt").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn makes_one_directory_at_a_time() {
+        let config = fixture("mkcol");
+        std::fs::write(config.root.join("a.txt"), "x").unwrap();
+        assert_eq!(send(&config, "MKCOL", "/dir", &[]).await, 201);
+        assert!(config.root.join("dir").is_dir());
+        assert_eq!(send(&config, "MKCOL", "/dir", &[]).await, 405);
+        assert_eq!(send(&config, "MKCOL", "/a.txt", &[]).await, 405);
+        assert_eq!(send(&config, "MKCOL", "/missing/dir", &[]).await, 409);
+        assert_eq!(send(&config, "MKCOL", "/.hidden", &[]).await, 404);
+        assert_eq!(send(&config, "MKCOL", "/../outside", &[]).await, 403);
+        std::fs::remove_dir_all(&config.root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn moves_within_the_root() {
+        let mut config = fixture("move");
+        let root = config.root.clone();
+        std::fs::create_dir_all(root.join("dir/sub")).unwrap();
+        std::fs::write(root.join("a.txt"), "a").unwrap();
+        std::fs::write(root.join("b.txt"), "b").unwrap();
+        std::fs::write(root.join(".env"), "secret").unwrap();
+        let to = |destination: &'static str| [("destination", destination)];
+
+        assert_eq!(
+            send(&config, "MOVE", "/dir", &to("/dir/sub/dir")).await,
+            409
+        );
+        assert_eq!(send(&config, "MOVE", "/", &to("/root")).await, 403);
+        assert_eq!(send(&config, "MOVE", "/a.txt", &to("/")).await, 409);
+        assert_eq!(
+            send(&config, "MOVE", "/a.txt", &to("http://evil.example/a.txt")).await,
+            403
+        );
+        assert_eq!(send(&config, "MOVE", "/a.txt", &to("/../a.txt")).await, 403);
+        assert_eq!(send(&config, "MOVE", "/a.txt", &[]).await, 400);
+        //hidden on either end, the way reads see them
+        assert_eq!(send(&config, "MOVE", "/.env", &to("/env.txt")).await, 404);
+        assert_eq!(send(&config, "MOVE", "/a.txt", &to("/.env")).await, 404);
+        assert_eq!(read(&config, ".env"), "secret");
+
+        //an existing destination needs --overwrite, and Overwrite: F refuses it anyway
+        assert_eq!(send(&config, "MOVE", "/a.txt", &to("/b.txt")).await, 409);
+        let keep = [("destination", "/b.txt"), ("overwrite", "F")];
+        assert_eq!(send(&config, "MOVE", "/a.txt", &keep).await, 412);
+        config.overwrite = true;
+        assert_eq!(send(&config, "MOVE", "/a.txt", &keep).await, 412);
+        assert_eq!(read(&config, "b.txt"), "b");
+        assert_eq!(send(&config, "MOVE", "/a.txt", &to("/b.txt")).await, 204);
+        assert_eq!(read(&config, "b.txt"), "a");
+
+        assert_eq!(
+            send(&config, "MOVE", "/b.txt", &to("http://localhost/dir/c.txt")).await,
+            201
+        );
+        assert_eq!(read(&config, "dir/c.txt"), "a");
+        assert_eq!(send(&config, "MOVE", "/dir", &to("/moved")).await, 201);
+        assert!(root.join("moved/sub").is_dir() && !root.join("dir").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}